@@ -0,0 +1,434 @@
+//! Delivery backends and the ordered fallback chain that drives them.
+
+use std::cell::Cell;
+use std::fmt;
+use std::time::Duration;
+
+use anyhow::{Result, anyhow, bail};
+use clap::ValueEnum;
+use futures_util::future::join_all;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::dbus::Connections;
+use crate::{AwaitTimeoutError, Request, dbus, osc, portal, schedule, sink, spool, tty};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Backend {
+    /// org.freedesktop.Notifications on the selected bus
+    Dbus,
+    /// org.freedesktop.portal.Notification (for Flatpak sandboxes)
+    Portal,
+    /// interactive prompt on the controlling tty
+    Tty,
+    /// terminal escape sequences (OSC 9 / 777 / 99) on the controlling tty
+    Osc,
+    /// append a JSON line to a file
+    File,
+}
+
+impl Backend {
+    pub(crate) const DEFAULT_CHAIN: [Backend; 2] = [Backend::Dbus, Backend::Osc];
+}
+
+impl fmt::Display for Backend {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let value = self.to_possible_value().expect("no skipped variants");
+        formatter.write_str(value.get_name())
+    }
+}
+
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub(crate) enum YamlBackends {
+    One(Backend),
+    Chain(Vec<Backend>),
+}
+
+impl YamlBackends {
+    pub(crate) fn into_vec(self) -> Vec<Backend> {
+        match self {
+            Self::One(backend) => vec![backend],
+            Self::Chain(backends) => backends,
+        }
+    }
+}
+
+/// A notification that was handed to a backend, plus whatever that backend
+/// needs to keep around to await the user's response.
+pub(crate) struct Delivery {
+    pub(crate) id: u32,
+    pub(crate) pending: Option<Pending>,
+}
+
+pub(crate) enum Pending {
+    Dbus(Box<dbus::Pending>),
+    Portal(Box<portal::Pending>),
+    Tty(tty::Pending),
+}
+
+impl Pending {
+    async fn wait(&mut self, events: &EventReporter) -> Result<()> {
+        match self {
+            Self::Dbus(pending) => pending.wait(events).await,
+            Self::Portal(pending) => pending.wait(events).await,
+            Self::Tty(pending) => pending.wait(events).await,
+        }
+    }
+
+    async fn finish(self) {
+        if let Self::Portal(pending) = self {
+            pending.finish().await;
+        }
+    }
+}
+
+/// One line of `--await`, batch and `notify flush` output.
+#[derive(Debug, Serialize, JsonSchema)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+pub(crate) enum Event {
+    /// an action was invoked; JSON action keys are decoded into `action_data`
+    Action {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        action: Option<String>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        action_data: Option<serde_json::Value>,
+    },
+    /// the notification was closed (1: expired, 2: dismissed, 3: closed by a call, 4: undefined)
+    Closed { reason: u32 },
+    /// the --await cap was reached
+    AwaitTimeout { timeout_ms: u64 },
+    /// the notification server went away during --await
+    ServerLost,
+    /// the notification was sent again to a new server; `id` is the new id
+    Resent { previous_id: u32 },
+    /// a batch document was delivered
+    Sent,
+    /// a batch document failed
+    Error { error: String },
+    /// a batch document was spooled because no backend delivered it
+    Spooled { spool: String },
+    /// `notify flush` redelivered a spooled notification
+    Delivered { spool: String },
+    /// `notify flush` discarded an expired spooled notification
+    Expired { spool: String },
+    /// the notification was handed to the scheduler; `schedule` is the id for `notify scheduled cancel`
+    Scheduled { schedule: String, deliver_at: String },
+}
+
+/// An event plus the fields every event line may carry.
+#[derive(Debug, Serialize, JsonSchema)]
+#[schemars(title = "notify event")]
+pub(crate) struct EventLine {
+    #[serde(flatten)]
+    pub(crate) event: Event,
+    /// notification id (with --print-id, in batches, after a resend and in flush output)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) id: Option<u32>,
+    /// delivering backend, when a chain of several backends was configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) backend: Option<Backend>,
+    /// 1-based document number in a batch
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub(crate) document: Option<usize>,
+}
+
+impl EventLine {
+    pub(crate) fn new(event: Event) -> Self {
+        Self {
+            event,
+            id: None,
+            backend: None,
+            document: None,
+        }
+    }
+
+    pub(crate) fn print(&self) {
+        // Through `Value`, so keys keep coming out sorted.
+        let output = serde_json::to_value(self).expect("event lines serialize to JSON");
+        println!("{output}");
+    }
+}
+
+/// Prints `--print-id` and `--await` output; the delivering backend is only
+/// reported when an explicit chain of more than one backend was configured.
+/// In a batch every event also carries the 1-based document number.
+pub(crate) struct EventReporter {
+    id: Cell<u32>,
+    print_id: bool,
+    backend: Option<Backend>,
+    document: Option<usize>,
+}
+
+impl EventReporter {
+    pub(crate) fn action(&self, action_key: String) {
+        let event = match serde_json::from_str::<serde_json::Value>(&action_key) {
+            Ok(action_data) => Event::Action {
+                action: None,
+                action_data: Some(action_data),
+            },
+            Err(_) => Event::Action {
+                action: Some(action_key),
+                action_data: None,
+            },
+        };
+        self.event(event);
+    }
+
+    pub(crate) fn closed(&self, reason: u32) {
+        self.event(Event::Closed { reason });
+    }
+
+    /// Switches to the id of a resent notification and reports it.
+    pub(crate) fn resent(&self, id: u32) {
+        let previous_id = self.id.replace(id);
+        let mut line = self.line(Event::Resent { previous_id });
+        line.id = Some(id);
+        line.print();
+    }
+
+    pub(crate) fn event(&self, event: Event) {
+        self.line(event).print();
+    }
+
+    fn line(&self, event: Event) -> EventLine {
+        EventLine {
+            event,
+            id: self.print_id.then(|| self.id.get()),
+            backend: self.backend,
+            document: self.document,
+        }
+    }
+
+    fn print_id(&self) {
+        if !self.print_id {
+            return;
+        }
+        match self.backend {
+            Some(backend) => println!("{}\t{backend}", self.id.get()),
+            None => println!("{}", self.id.get()),
+        }
+    }
+}
+
+pub(crate) async fn deliver(mut request: Request) -> Result<()> {
+    request.attach_full_text()?;
+    if let Some(due) = request.deliver_at {
+        // Only an in-process wait can print the id or the await result.
+        if !(request.await_result || request.print_id) {
+            let id = schedule::add(&request, due)?;
+            EventLine::new(Event::Scheduled {
+                schedule: id,
+                deliver_at: schedule::format_time(due),
+            })
+            .print();
+            return Ok(());
+        }
+        schedule::sleep_until(due).await;
+    }
+    let (backend, delivery) = match dispatch(&request, &Connections::default()).await {
+        Ok(delivered) => delivered,
+        Err(error) if request.spool => {
+            let path = spool::write(&request)?;
+            eprintln!(
+                "warning: {error:#}; notification spooled to {}",
+                path.display()
+            );
+            return Ok(());
+        }
+        Err(error) => return Err(error),
+    };
+
+    let events = EventReporter {
+        id: Cell::new(delivery.id),
+        print_id: request.print_id,
+        backend: request.report_backend.then_some(backend),
+        document: None,
+    };
+    events.print_id();
+
+    match delivery.pending {
+        Some(pending) => wait_and_finish(&events, request.await_timeout_ms, pending).await,
+        None => Ok(()),
+    }
+}
+
+/// Sends every document of a batch in order over shared bus connections and
+/// prints one result line per document. Awaited notifications are waited for
+/// one by one before the next is sent, or with `await_all` all at once after
+/// everything was sent, printing events as they arrive.
+pub(crate) async fn deliver_batch(requests: Vec<Result<Request>>, await_all: bool) -> Result<()> {
+    let connections = Connections::default();
+    let total = requests.len();
+    let mut failed = 0;
+    let mut awaiting = Vec::new();
+
+    for (index, request) in requests.into_iter().enumerate() {
+        let document = index + 1;
+        let report_error = |error: &anyhow::Error| {
+            let mut line = EventLine::new(Event::Error {
+                error: format!("{error:#}"),
+            });
+            line.document = Some(document);
+            line.print();
+        };
+
+        let request = match request.and_then(|mut request| {
+            request.attach_full_text()?;
+            Ok(request)
+        }) {
+            Ok(request) => request,
+            Err(error) => {
+                report_error(&error);
+                failed += 1;
+                continue;
+            }
+        };
+
+        if let Some(due) = request.deliver_at {
+            if !(request.await_result || request.print_id) {
+                match schedule::add(&request, due) {
+                    Ok(id) => {
+                        let mut line = EventLine::new(Event::Scheduled {
+                            schedule: id,
+                            deliver_at: schedule::format_time(due),
+                        });
+                        line.document = Some(document);
+                        line.print();
+                    }
+                    Err(error) => {
+                        report_error(&error);
+                        failed += 1;
+                    }
+                }
+                continue;
+            }
+            schedule::sleep_until(due).await;
+        }
+
+        let (backend, delivery) = match dispatch(&request, &connections).await {
+            Ok(delivered) => delivered,
+            Err(error) if request.spool => match spool::write(&request) {
+                Ok(path) => {
+                    eprintln!("warning: document {document}: {error:#}");
+                    let mut line = EventLine::new(Event::Spooled {
+                        spool: path.display().to_string(),
+                    });
+                    line.document = Some(document);
+                    line.print();
+                    continue;
+                }
+                Err(error) => {
+                    report_error(&error);
+                    failed += 1;
+                    continue;
+                }
+            },
+            Err(error) => {
+                report_error(&error);
+                failed += 1;
+                continue;
+            }
+        };
+
+        let events = EventReporter {
+            id: Cell::new(delivery.id),
+            print_id: true,
+            backend: request.report_backend.then_some(backend),
+            document: Some(document),
+        };
+        events.event(Event::Sent);
+
+        let Some(pending) = delivery.pending else {
+            continue;
+        };
+        if await_all {
+            awaiting.push((events, request.await_timeout_ms, pending));
+        } else if let Err(error) = wait_and_finish(&events, request.await_timeout_ms, pending).await {
+            events.event(Event::Error {
+                error: format!("{error:#}"),
+            });
+            failed += 1;
+        }
+    }
+
+    let results = join_all(awaiting.iter_mut().map(|(events, await_timeout, pending)| {
+        with_await_cap(events, *await_timeout, pending.wait(events))
+    }))
+    .await;
+    for ((events, _, pending), result) in awaiting.into_iter().zip(results) {
+        pending.finish().await;
+        if let Err(error) = result {
+            events.event(Event::Error {
+                error: format!("{error:#}"),
+            });
+            failed += 1;
+        }
+    }
+
+    if failed > 0 {
+        bail!("{failed} of {total} notification(s) failed");
+    }
+    Ok(())
+}
+
+async fn wait_and_finish(
+    events: &EventReporter,
+    await_timeout: Option<u64>,
+    mut pending: Pending,
+) -> Result<()> {
+    let result = with_await_cap(events, await_timeout, pending.wait(events)).await;
+    pending.finish().await;
+    result
+}
+
+/// Tries each backend of the chain in order and returns the first that delivered.
+pub(crate) async fn dispatch(request: &Request, connections: &Connections) -> Result<(Backend, Delivery)> {
+    let mut failures = Vec::new();
+    let backends = &request.backends;
+
+    for (index, backend) in backends.iter().copied().enumerate() {
+        let result = match backend {
+            Backend::Dbus => dbus::deliver(request, connections).await,
+            Backend::Portal => portal::deliver(request, connections).await,
+            Backend::Tty => tty::deliver(request),
+            Backend::Osc => osc::deliver(request),
+            Backend::File => sink::deliver(request),
+        };
+
+        match result {
+            Ok(delivery) => return Ok((backend, delivery)),
+            Err(error) if backends.len() == 1 => return Err(error),
+            Err(error) => {
+                if let Some(next) = backends.get(index + 1) {
+                    eprintln!("warning: {backend} backend failed: {error:#}; trying {next}");
+                }
+                failures.push(format!("{backend}: {error:#}"));
+            }
+        }
+    }
+
+    Err(anyhow!(
+        "no backend delivered the notification ({})",
+        failures.join("; ")
+    ))
+}
+
+async fn with_await_cap(
+    events: &EventReporter,
+    await_timeout: Option<u64>,
+    wait_future: impl Future<Output = Result<()>>,
+) -> Result<()> {
+    if let Some(ms) = await_timeout {
+        match tokio::time::timeout(Duration::from_millis(ms), wait_future).await {
+            Ok(result) => result,
+            Err(_) => {
+                events.event(Event::AwaitTimeout { timeout_ms: ms });
+                Err(AwaitTimeoutError { timeout_ms: ms }.into())
+            }
+        }
+    } else {
+        wait_future.await
+    }
+}
@@ -0,0 +1,243 @@
+//! Payload bodies read from a file (`body_file`) or from a command's output
+//! (`body_command`, run without a shell), cut to a byte limit and optionally
+//! to their first or last lines.
+
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+use std::process::{Child, ChildStdout, Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, Result, anyhow, bail};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+use crate::duration::Timeout;
+
+/// Default for `body_max_bytes`.
+pub(crate) const DEFAULT_MAX_BYTES: u64 = 64 * 1024;
+
+/// Default for `body_timeout`.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// command argument; numbers need no quotes, e.g. [tail, -n, 20, app.log]
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(untagged)]
+pub(crate) enum CommandArg {
+    Text(String),
+    Number(serde_json::Number),
+}
+
+impl CommandArg {
+    fn to_arg(&self) -> String {
+        match self {
+            Self::Text(text) => text.clone(),
+            Self::Number(number) => number.to_string(),
+        }
+    }
+}
+
+/// Which part of the text becomes the body.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Selection {
+    pub(crate) head: Option<usize>,
+    pub(crate) tail: Option<usize>,
+    pub(crate) max_bytes: u64,
+}
+
+impl Selection {
+    /// With `tail`, the last `max_bytes` are kept instead of the first.
+    fn keeps_end(&self) -> bool {
+        self.tail.is_some()
+    }
+
+    fn apply(&self, bytes: &[u8], cut: bool) -> String {
+        let mut text = String::from_utf8_lossy(bytes).into_owned();
+        // A cut at the start leaves a partial first line; drop it.
+        if cut
+            && self.keeps_end()
+            && let Some(newline) = text.find('\n')
+        {
+            text.drain(..=newline);
+        }
+        let lines = text.lines();
+        let selected = match (self.head, self.tail) {
+            (Some(head), _) => lines.take(head).collect::<Vec<_>>(),
+            (None, Some(tail)) => {
+                let lines = lines.collect::<Vec<_>>();
+                lines[lines.len().saturating_sub(tail)..].to_vec()
+            }
+            (None, None) => return text.trim_end_matches('\n').to_string(),
+        };
+        selected.join("\n")
+    }
+}
+
+pub(crate) fn read_file(path: &Path, selection: &Selection) -> Result<String> {
+    let mut file = File::open(path)
+        .with_context(|| format!("failed to open body_file: {}", path.display()))?;
+    let length = file
+        .metadata()
+        .with_context(|| format!("failed to inspect body_file: {}", path.display()))?
+        .len();
+    let cut = length > selection.max_bytes;
+    if cut && selection.keeps_end() {
+        file.seek(SeekFrom::End(-(selection.max_bytes as i64)))
+            .with_context(|| format!("failed to seek in body_file: {}", path.display()))?;
+    }
+    let mut bytes = Vec::new();
+    file.take(selection.max_bytes)
+        .read_to_end(&mut bytes)
+        .with_context(|| format!("failed to read body_file: {}", path.display()))?;
+    Ok(selection.apply(&bytes, cut))
+}
+
+/// Runs `argv` with stdin closed and stderr passed through, and returns its
+/// selected stdout. Output past the byte limit is discarded (with `tail`, the
+/// oldest output is). The command is killed once `timeout` (default 10s) has
+/// passed.
+pub(crate) fn run_command(argv: &[CommandArg], selection: &Selection, timeout: Option<Timeout>) -> Result<String> {
+    let argv = argv.iter().map(CommandArg::to_arg).collect::<Vec<_>>();
+    let Some((program, args)) = argv.split_first() else {
+        bail!("body_command must name a program");
+    };
+    let name = argv.join(" ");
+    let timeout = match timeout.unwrap_or(Timeout::Default) {
+        Timeout::Default => Some(DEFAULT_TIMEOUT),
+        Timeout::Never => None,
+        Timeout::After(millis) => Some(Duration::from_millis(millis)),
+    };
+    let deadline = timeout.map(|timeout| Instant::now() + timeout);
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .with_context(|| format!("failed to run body_command '{name}'"))?;
+
+    // Read on another thread, so a command that hangs without writing can be
+    // stopped at the deadline.
+    let stdout = child.stdout.take().expect("stdout is piped");
+    let limit = selection.max_bytes as usize;
+    let keeps_end = selection.keeps_end();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || sender.send(read_output(stdout, limit, keeps_end)));
+    let output = match deadline {
+        Some(deadline) => receiver.recv_timeout(deadline.saturating_duration_since(Instant::now())),
+        None => receiver.recv().map_err(|_| RecvTimeoutError::Disconnected),
+    };
+    let (mut kept, cut) = match output {
+        Ok(output) => output.with_context(|| format!("failed to read output of body_command '{name}'"))?,
+        Err(RecvTimeoutError::Timeout) => return Err(kill(child, &name, timeout)),
+        Err(RecvTimeoutError::Disconnected) => bail!("failed to read output of body_command '{name}'"),
+    };
+
+    // Reading stops at the byte limit; a command still writing then gets
+    // SIGPIPE, one that ignores it is stopped at the deadline.
+    let status = loop {
+        if let Some(status) = child
+            .try_wait()
+            .with_context(|| format!("failed to wait for body_command '{name}'"))?
+        {
+            break status;
+        }
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(kill(child, &name, timeout));
+        }
+        thread::sleep(Duration::from_millis(10));
+    };
+    if !status.success() && !cut {
+        bail!("body_command '{name}' failed ({status})");
+    }
+    Ok(selection.apply(kept.make_contiguous(), cut))
+}
+
+/// Stdout up to `limit` bytes (the last ones with `keeps_end`), and whether
+/// there was more.
+fn read_output(mut stdout: ChildStdout, limit: usize, keeps_end: bool) -> io::Result<(VecDeque<u8>, bool)> {
+    let mut kept = VecDeque::<u8>::new();
+    let mut cut = false;
+    let mut buffer = [0_u8; 8192];
+    loop {
+        let read = stdout.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        kept.extend(&buffer[..read]);
+        if kept.len() > limit {
+            cut = true;
+            if !keeps_end {
+                kept.truncate(limit);
+                break;
+            }
+            kept.drain(..kept.len() - limit);
+        }
+    }
+    Ok((kept, cut))
+}
+
+fn kill(mut child: Child, name: &str, timeout: Option<Duration>) -> anyhow::Error {
+    let _ = child.kill();
+    let _ = child.wait();
+    let timeout = timeout.unwrap_or_default();
+    anyhow!(
+        "body_command '{name}' did not finish within {}; raise body_timeout if it needs longer",
+        Timeout::After(timeout.as_millis() as u64)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn selection(head: Option<usize>, tail: Option<usize>) -> Selection {
+        Selection {
+            head,
+            tail,
+            max_bytes: DEFAULT_MAX_BYTES,
+        }
+    }
+
+    #[test]
+    fn whole_text_loses_trailing_newlines() {
+        assert_eq!(selection(None, None).apply(b"one\ntwo\n\n", false), "one\ntwo");
+    }
+
+    #[test]
+    fn head_and_tail_pick_lines() {
+        let text = b"one\ntwo\nthree\nfour\n";
+        assert_eq!(selection(Some(2), None).apply(text, false), "one\ntwo");
+        assert_eq!(selection(None, Some(2)).apply(text, false), "three\nfour");
+        assert_eq!(selection(None, Some(10)).apply(text, false), "one\ntwo\nthree\nfour");
+        assert_eq!(selection(Some(0), None).apply(text, false), "");
+    }
+
+    #[test]
+    fn cut_from_the_end_drops_the_partial_first_line() {
+        assert_eq!(selection(None, Some(5)).apply(b"ee\nthree\nfour", true), "three\nfour");
+        // Cut from the start, the first line is whole.
+        assert_eq!(selection(Some(5), None).apply(b"one\ntwo\nthr", true), "one\ntwo\nthr");
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        assert_eq!(selection(None, None).apply(b"caf\xc3", false), "caf\u{fffd}");
+    }
+
+    #[test]
+    fn command_output_and_timeout() {
+        let argv = |args: &[&str]| args.iter().map(|arg| CommandArg::Text(arg.to_string())).collect::<Vec<_>>();
+        let output = run_command(&argv(&["printf", "a\\nb\\nc\\n"]), &selection(None, Some(1)), None).unwrap();
+        assert_eq!(output, "c");
+
+        let started = Instant::now();
+        let error = run_command(&argv(&["sleep", "5"]), &selection(None, None), Some(Timeout::After(200)))
+            .expect_err("sleep outlives the timeout");
+        assert!(format!("{error:#}").contains("did not finish within 200ms"), "{error:#}");
+        assert!(started.elapsed() < Duration::from_secs(2));
+    }
+}
@@ -0,0 +1,333 @@
+//! User configuration: global defaults and named profiles that layer
+//! underneath the YAML payload (CLI > payload > template > profile > defaults).
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::PathBuf;
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde::Deserialize;
+use serde_yaml::{Mapping, Value};
+
+use crate::{YamlPayload, config_dir, validate, yaml_tree};
+
+/// Payload keys that are aliases of another key; the alias wins within a
+/// layer, matching how `merge_request` treats them.
+const KEY_ALIASES: [(&str, &str); 2] = [("expire_time", "timeout"), ("replace", "id")];
+
+#[derive(Debug, Default, Deserialize)]
+pub(crate) struct Config {
+    #[serde(default)]
+    defaults: Mapping,
+    #[serde(default)]
+    profiles: BTreeMap<String, Mapping>,
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Source {
+    Defaults,
+    Profile(String),
+    Template(String),
+    Payload,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Defaults => formatter.write_str("defaults"),
+            Self::Profile(name) => write!(formatter, "profile {name}"),
+            Self::Template(name) => write!(formatter, "template {name}"),
+            Self::Payload => formatter.write_str("payload"),
+        }
+    }
+}
+
+/// The merged payload mapping, with the layer each key (and each hint) came from.
+pub(crate) struct Layered {
+    mapping: Mapping,
+    sources: BTreeMap<String, Source>,
+}
+
+impl Layered {
+    pub(crate) fn source(&self, key: &str) -> Option<&Source> {
+        self.sources.get(key)
+    }
+
+    pub(crate) fn into_payload(self) -> Result<YamlPayload> {
+        serde_yaml::from_value(Value::Mapping(self.mapping)).context("failed to parse YAML payload")
+    }
+}
+
+pub(crate) fn config_path() -> Result<PathBuf> {
+    if let Some(path) = std::env::var_os("NOTIFY_CONFIG").filter(|value| !value.is_empty()) {
+        return Ok(PathBuf::from(path));
+    }
+    Ok(config_dir()?.join("notify").join("config.yaml"))
+}
+
+impl Config {
+    pub(crate) fn load() -> Result<Self> {
+        let path = config_path()?;
+        let input = match std::fs::read_to_string(&path) {
+            Ok(input) => input,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(error) => {
+                return Err(error)
+                    .with_context(|| format!("failed to read config file: {}", path.display()));
+            }
+        };
+
+        let mut config: Config = if input.trim().is_empty() {
+            Config::default()
+        } else {
+            let context = || format!("failed to parse config file: {}", path.display());
+            for document in yaml_tree::parse_documents(&input).with_context(context)? {
+                let problems = validate::check_config(&document);
+                validate::ensure_valid(&problems, format_args!("config file {}", path.display()))?;
+            }
+            serde_yaml::from_str(&input).with_context(context)?
+        };
+        config.path = Some(path);
+        Ok(config)
+    }
+
+    /// Layers `payload` over the rendered template over the selected profile over
+    /// the defaults. The profile comes from `profile` (the CLI), or else a
+    /// `profile` key in the payload or template.
+    pub(crate) fn layer(
+        &self,
+        profile: Option<&str>,
+        template: Option<(&str, Mapping)>,
+        payload: Option<Value>,
+    ) -> Result<Layered> {
+        let payload = match payload {
+            Some(Value::Mapping(mapping)) => mapping,
+            Some(Value::Null) | None => Mapping::new(),
+            Some(_) => bail!("failed to parse YAML payload: expected a mapping at the top level"),
+        };
+
+        let mut payload_profile = None;
+        for (layer, source) in [
+            (Some(&payload), "payload"),
+            (template.as_ref().map(|(_, template)| template), "template"),
+        ] {
+            match layer.and_then(|layer| layer.get("profile")) {
+                Some(Value::String(name)) => {
+                    payload_profile = payload_profile.or(Some(name.as_str()));
+                }
+                Some(_) => bail!("{source} 'profile' must be a string"),
+                None => {}
+            }
+        }
+
+        let mut layered = Layered {
+            mapping: Mapping::new(),
+            sources: BTreeMap::new(),
+        };
+        apply_layer(&mut layered, &self.defaults, &Source::Defaults)?;
+        if let Some(name) = profile.or(payload_profile) {
+            let profile = self.profiles.get(name).ok_or_else(|| {
+                let available = self.profiles.keys().cloned().collect::<Vec<_>>();
+                if available.is_empty() {
+                    anyhow!("unknown profile '{name}' (no profiles are configured)")
+                } else {
+                    anyhow!("unknown profile '{name}' (available: {})", available.join(", "))
+                }
+            })?;
+            apply_layer(&mut layered, profile, &Source::Profile(name.to_string()))?;
+        }
+        if let Some((name, template)) = &template {
+            apply_layer(&mut layered, template, &Source::Template(name.to_string()))?;
+        }
+        apply_layer(&mut layered, &payload, &Source::Payload)?;
+        layered.mapping.remove("profile");
+        layered.sources.remove("profile");
+        Ok(layered)
+    }
+}
+
+fn apply_layer(layered: &mut Layered, layer: &Mapping, source: &Source) -> Result<()> {
+    let mut layer = layer.clone();
+    for (alias, canonical) in KEY_ALIASES {
+        if let Some(value) = layer.remove(alias) {
+            layer.insert(Value::from(canonical), value);
+        }
+    }
+
+    for (key, value) in layer {
+        let Value::String(key) = key else {
+            bail!("{source}: keys must be strings");
+        };
+
+        if key == "hints" {
+            let hints = match value {
+                Value::Mapping(hints) => hints,
+                Value::Null => continue,
+                _ => bail!("{source}: 'hints' must be a mapping"),
+            };
+            let merged = layered
+                .mapping
+                .entry(Value::from("hints"))
+                .or_insert_with(|| Value::Mapping(Mapping::new()));
+            let Value::Mapping(merged) = merged else {
+                unreachable!("hints are always stored as a mapping");
+            };
+            for (hint_key, hint_value) in hints {
+                let name = hint_key.as_str().unwrap_or_default().to_string();
+                merged.insert(hint_key, hint_value);
+                layered.sources.insert(format!("hints.{name}"), source.clone());
+            }
+            continue;
+        }
+
+        layered.sources.insert(key.clone(), source.clone());
+        layered.mapping.insert(Value::String(key), value);
+    }
+    Ok(())
+}
+
+/// `notify config show`: prints the effective settings and where each came from.
+pub(crate) fn show(profile: Option<&str>) -> Result<()> {
+    let config = Config::load()?;
+    let layered = config.layer(profile, None, None)?;
+
+    match &config.path {
+        Some(path) => println!("# config: {}", path.display()),
+        None => println!("# config: {} (not found)", config_path()?.display()),
+    }
+    if let Some(name) = profile {
+        println!("# profile: {name}");
+    }
+    if !config.profiles.is_empty() {
+        let names = config.profiles.keys().cloned().collect::<Vec<_>>();
+        println!("# profiles: {}", names.join(", "));
+    }
+    println!("# precedence: CLI > payload > template > profile > defaults");
+
+    let mut entries = layered.mapping.iter().collect::<Vec<_>>();
+    entries.sort_by(|(left, _), (right, _)| left.as_str().cmp(&right.as_str()));
+    for (key, value) in entries {
+        let key = key.as_str().unwrap_or_default();
+        if let Value::Mapping(hints) = value
+            && key == "hints"
+        {
+            println!("hints:");
+            let mut hints = hints.iter().collect::<Vec<_>>();
+            hints.sort_by(|(left, _), (right, _)| left.as_str().cmp(&right.as_str()));
+            for (hint_key, hint_value) in hints {
+                let hint_key = hint_key.as_str().unwrap_or_default();
+                let source = &layered.sources[&format!("hints.{hint_key}")];
+                println!("  {hint_key}: {}  # {source}", inline_yaml(hint_value)?);
+            }
+            continue;
+        }
+        println!("{key}: {}  # {}", inline_yaml(value)?, layered.sources[key]);
+    }
+    Ok(())
+}
+
+fn inline_yaml(value: &Value) -> Result<String> {
+    match value {
+        Value::Mapping(_) | Value::Sequence(_) | Value::Tagged(_) => {
+            Ok(serde_json::to_string(value).context("failed to render config value")?)
+        }
+        _ => Ok(serde_yaml::to_string(value)
+            .context("failed to render config value")?
+            .trim_end()
+            .to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "
+defaults:
+  urgency: low
+  app_name: base
+  hints:
+    x-a: 1
+    x-b: 1
+profiles:
+  work:
+    urgency: normal
+    expire_time: 5
+    hints:
+      x-b: 2
+  home:
+    category: im
+";
+
+    fn config() -> Config {
+        serde_yaml::from_str(CONFIG).unwrap()
+    }
+
+    fn mapping(yaml: &str) -> Mapping {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    fn get<'a>(layered: &'a Layered, key: &str) -> &'a Value {
+        &layered.mapping[key]
+    }
+
+    #[test]
+    fn layers_in_precedence_order() {
+        let template = mapping("urgency: critical\nsummary: from template\nid: 3");
+        let payload = Value::Mapping(mapping("summary: from payload\nreplace: 9"));
+        let layered = config().layer(Some("work"), Some(("deploy", template)), Some(payload)).unwrap();
+
+        assert_eq!(get(&layered, "app_name"), "base");
+        assert_eq!(layered.source("app_name"), Some(&Source::Defaults));
+        // The profile's alias lands on the canonical key.
+        assert_eq!(get(&layered, "timeout"), 5);
+        assert_eq!(layered.source("timeout"), Some(&Source::Profile("work".to_string())));
+        assert_eq!(get(&layered, "urgency"), "critical");
+        assert_eq!(layered.source("urgency"), Some(&Source::Template("deploy".to_string())));
+        assert_eq!(get(&layered, "summary"), "from payload");
+        assert_eq!(get(&layered, "id"), 9);
+        assert_eq!(layered.source("id"), Some(&Source::Payload));
+    }
+
+    #[test]
+    fn hints_merge_per_key() {
+        let payload = Value::Mapping(mapping("hints: {x-c: 3}"));
+        let layered = config().layer(Some("work"), None, Some(payload)).unwrap();
+        let hints = get(&layered, "hints");
+        assert_eq!((&hints["x-a"], &hints["x-b"], &hints["x-c"]), (&1.into(), &2.into(), &3.into()));
+        assert_eq!(layered.source("hints.x-a"), Some(&Source::Defaults));
+        assert_eq!(layered.source("hints.x-b"), Some(&Source::Profile("work".to_string())));
+        assert_eq!(layered.source("hints.x-c"), Some(&Source::Payload));
+    }
+
+    #[test]
+    fn profile_selection() {
+        let config = config();
+        // The payload's profile beats the template's, the CLI beats both.
+        let template = || Some(("t", mapping("profile: work")));
+        let payload = || Some(Value::Mapping(mapping("profile: home")));
+        let layered = config.layer(None, template(), payload()).unwrap();
+        assert_eq!(get(&layered, "category"), "im");
+        assert!(layered.source("profile").is_none() && !layered.mapping.contains_key("profile"));
+        let layered = config.layer(None, template(), None).unwrap();
+        assert_eq!(get(&layered, "urgency"), "normal");
+        let layered = config.layer(Some("work"), None, payload()).unwrap();
+        assert_eq!(get(&layered, "urgency"), "normal");
+
+        let error = config.layer(Some("gym"), None, None).err().unwrap();
+        assert_eq!(error.to_string(), "unknown profile 'gym' (available: home, work)");
+        let error = Config::default().layer(Some("gym"), None, None).err().unwrap();
+        assert_eq!(error.to_string(), "unknown profile 'gym' (no profiles are configured)");
+        let error = config.layer(None, None, Some(Value::Mapping(mapping("profile: 1")))).err().unwrap();
+        assert_eq!(error.to_string(), "payload 'profile' must be a string");
+    }
+
+    #[test]
+    fn payload_must_be_a_mapping() {
+        assert!(config().layer(None, None, Some(Value::Null)).is_ok());
+        let error = config().layer(None, None, Some(Value::from("text"))).err().unwrap();
+        assert!(error.to_string().contains("expected a mapping"));
+    }
+}
@@ -0,0 +1,607 @@
+//! Delivery through `org.freedesktop.Notifications`.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+
+use anyhow::{Context, Result, anyhow, bail};
+use clap::ValueEnum;
+use futures_util::{FutureExt, StreamExt};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use zbus::fdo::{DBusProxy, NameOwnerChangedStream};
+use zbus::{MatchRule, Message, MessageStream, Proxy, message};
+use zvariant::{OwnedValue, Str, Value};
+
+use crate::backend::{Delivery, Event, EventLine, EventReporter, Pending as BackendPending};
+use crate::{Bus, NOTIFY_IFACE, NOTIFY_PATH, Request, ServerLostError, markup, schedule, signing};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum ServerLostPolicy {
+    /// print a server-lost event and exit with code 69
+    #[default]
+    Fail,
+    /// send the notification again once a new server appears
+    Resend,
+}
+
+pub(crate) async fn connect_bus(bus: &Bus) -> Result<zbus::Connection> {
+    match bus {
+        Bus::Session => zbus::Connection::session()
+            .await
+            .context("failed to connect to session D-Bus"),
+        Bus::System => zbus::Connection::system()
+            .await
+            .context("failed to connect to system D-Bus"),
+        Bus::Address(address) => zbus::connection::Builder::address(address.as_str())
+            .with_context(|| format!("invalid D-Bus address '{address}'"))?
+            .build()
+            .await
+            .with_context(|| format!("failed to connect to D-Bus at '{address}'")),
+    }
+}
+
+/// Bus connections opened so far, so every notification of a batch goes out
+/// over the same connection per bus, and the capabilities of each server.
+#[derive(Default)]
+pub(crate) struct Connections {
+    open: RefCell<Vec<(Bus, zbus::Connection)>>,
+    capabilities: RefCell<Vec<(Bus, String, Vec<String>)>>,
+    spec_versions: RefCell<Vec<(Bus, String, String)>>,
+}
+
+impl Connections {
+    pub(crate) async fn get(&self, bus: &Bus) -> Result<zbus::Connection> {
+        if let Some((_, connection)) = self.open.borrow().iter().find(|(open, _)| open == bus) {
+            return Ok(connection.clone());
+        }
+        let connection = connect_bus(bus).await?;
+        self.open
+            .borrow_mut()
+            .push((bus.clone(), connection.clone()));
+        Ok(connection)
+    }
+
+    /// `GetCapabilities` of the proxy's server, asked once per bus and name.
+    async fn capabilities(&self, bus: &Bus, proxy: &Proxy<'_>) -> Result<Vec<String>> {
+        let dest = proxy.destination().as_str();
+        if let Some((_, _, capabilities)) = self
+            .capabilities
+            .borrow()
+            .iter()
+            .find(|(known_bus, known_dest, _)| known_bus == bus && known_dest == dest)
+        {
+            return Ok(capabilities.clone());
+        }
+        let capabilities: Vec<String> = proxy
+            .call("GetCapabilities", &())
+            .await
+            .context("failed to query notification server capabilities")?;
+        self.capabilities
+            .borrow_mut()
+            .push((bus.clone(), dest.to_string(), capabilities.clone()));
+        Ok(capabilities)
+    }
+
+    /// The spec version from `GetServerInformation`, asked once per bus and name.
+    async fn spec_version(&self, bus: &Bus, proxy: &Proxy<'_>) -> Result<String> {
+        let dest = proxy.destination().as_str();
+        if let Some((_, _, version)) = self
+            .spec_versions
+            .borrow()
+            .iter()
+            .find(|(known_bus, known_dest, _)| known_bus == bus && known_dest == dest)
+        {
+            return Ok(version.clone());
+        }
+        let (_, _, _, version): (String, String, String, String) = proxy
+            .call("GetServerInformation", &())
+            .await
+            .context("failed to query notification server information")?;
+        self.spec_versions
+            .borrow_mut()
+            .push((bus.clone(), dest.to_string(), version.clone()));
+        Ok(version)
+    }
+}
+
+pub(crate) async fn deliver(request: &Request, connections: &Connections) -> Result<Delivery> {
+    let connection = connections.get(&request.bus).await?;
+
+    let proxy = Proxy::new(
+        &connection,
+        request.dest.clone(),
+        NOTIFY_PATH,
+        NOTIFY_IFACE,
+    )
+    .await
+    .context("failed to create notifications proxy")?;
+
+    // Without capabilities it is unknown whether the server parses markup;
+    // escaped plain text reads the same either way.
+    let mut markup_unknown = false;
+    let body_markup = if request.body_format.is_some() && !request.body.is_empty() {
+        match connections.capabilities(&request.bus, &proxy).await {
+            Ok(capabilities) => capabilities.iter().any(|capability| capability == "body-markup"),
+            Err(error) => {
+                eprintln!("warning: {error:#}; sending the body as escaped plain text");
+                markup_unknown = true;
+                false
+            }
+        }
+    } else {
+        false
+    };
+    let mut notification = NotifyArgs::from_request(request, body_markup)?;
+    if markup_unknown {
+        notification.body = markup::escape(&notification.body);
+    }
+    if notification.hints.contains_key("image-data") {
+        let image_data = match connections.spec_version(&request.bus, &proxy).await {
+            Ok(version) => supports_image_data(&version),
+            Err(error) => {
+                eprintln!("warning: {error:#}; sending the image as image-path");
+                false
+            }
+        };
+        if !image_data {
+            notification.use_image_path(request.image_path.as_deref());
+        }
+    }
+    if !request.await_result {
+        let notification_id = notification.send(&proxy, request.replaces_id).await?;
+        return Ok(Delivery {
+            id: notification_id,
+            pending: None,
+        });
+    }
+
+    // Subscribe before calling Notify so an action invoked right away is not missed.
+    let mut pending =
+        Pending::subscribe(&connection, proxy, notification, request.on_server_lost).await?;
+    let (id, sender) = pending
+        .notification
+        .send_from(&pending.proxy, request.replaces_id)
+        .await?;
+    pending.id = id;
+    pending.pin_owner(sender).await?;
+
+    Ok(Delivery {
+        id: pending.id,
+        pending: Some(BackendPending::Dbus(Box::new(pending))),
+    })
+}
+
+/// `--dry-run`: prints the `Notify` arguments of each request as a JSON line
+/// without connecting to the bus. The body is shown as sent to a server with
+/// `body-markup`. In a batch each line names its document and a failed document
+/// does not stop the others.
+pub(crate) fn dry_run(requests: Vec<Result<Request>>) -> Result<()> {
+    let batch = requests.len() > 1;
+    let mut failed = 0;
+
+    for (index, request) in requests.into_iter().enumerate() {
+        let call = request.and_then(|request| {
+            let mut call = NotifyArgs::from_request(&request, true)?.to_json(request.replaces_id);
+            if let Some(due) = request.deliver_at {
+                call["deliver_at"] = json!(schedule::format_time(due));
+            }
+            Ok(call)
+        });
+        match call {
+            Ok(mut call) => {
+                if batch {
+                    call["document"] = json!(index + 1);
+                }
+                println!("{call}");
+            }
+            Err(error) if batch => {
+                let mut line = EventLine::new(Event::Error {
+                    error: format!("{error:#}"),
+                });
+                line.document = Some(index + 1);
+                line.print();
+                failed += 1;
+            }
+            Err(error) => return Err(error),
+        }
+    }
+
+    if failed > 0 {
+        bail!("{failed} notification(s) failed");
+    }
+    Ok(())
+}
+
+/// The `Notify` arguments kept around so the notification can be resent.
+struct NotifyArgs {
+    app_name: String,
+    icon: String,
+    summary: String,
+    body: String,
+    actions: Vec<String>,
+    hints: HashMap<String, OwnedValue>,
+    expire_timeout: i32,
+    card_nonce: Option<String>,
+}
+
+impl NotifyArgs {
+    fn from_request(request: &Request, body_markup: bool) -> Result<Self> {
+        let mut actions = request.actions.clone();
+        if let Some(nonce) = &request.card_nonce {
+            for id in actions.iter_mut().step_by(2) {
+                *id = signing::with_nonce(id, nonce);
+            }
+        }
+
+        let mut hints = HashMap::with_capacity(request.hints.len());
+        for (key, value) in &request.hints {
+            let value = value
+                .try_clone()
+                .with_context(|| format!("failed to copy hint '{key}'"))?;
+            hints.insert(key.clone(), value);
+        }
+
+        Ok(Self {
+            app_name: request.app_name.clone(),
+            icon: request.icon.clone(),
+            summary: request.summary.clone(),
+            body: request.render_body(body_markup),
+            actions,
+            hints,
+            expire_timeout: request.expire_timeout,
+            card_nonce: request.card_nonce.clone(),
+        })
+    }
+
+    /// Servers before spec 1.2 do not know `image-data`; the image file is
+    /// passed as `image-path` instead (dropped if it came from stdin).
+    fn use_image_path(&mut self, image_path: Option<&str>) {
+        self.hints.remove("image-data");
+        if let Some(path) = image_path {
+            self.hints
+                .insert("image-path".to_string(), OwnedValue::from(Str::from(path)));
+        }
+    }
+
+    /// Hints carry their D-Bus signature; card bodies are also shown decoded.
+    fn to_json(&self, replaces_id: u32) -> serde_json::Value {
+        let hints = self
+            .hints
+            .iter()
+            .map(|(key, value)| {
+                let hint = json!({
+                    "signature": value.value_signature().to_string(),
+                    "value": variant_to_json(value),
+                });
+                (key.clone(), hint)
+            })
+            .collect::<serde_json::Map<_, _>>();
+
+        let mut call = json!({
+            "app_name": self.app_name,
+            "replaces_id": replaces_id,
+            "icon": self.icon,
+            "summary": self.summary,
+            "body": self.body,
+            "actions": self.actions,
+            "hints": hints,
+            "expire_timeout": self.expire_timeout,
+        });
+        if self.hints.contains_key("x-card")
+            && let Ok(card) = serde_json::from_str::<serde_json::Value>(&self.body)
+        {
+            call["card"] = card;
+        }
+        call
+    }
+
+    async fn send(&self, proxy: &Proxy<'_>, replaces_id: u32) -> Result<u32> {
+        self.send_from(proxy, replaces_id).await.map(|(id, _)| id)
+    }
+
+    /// Also returns the reply's sender: the unique name of the server that
+    /// holds the notification (none on a peer-to-peer connection).
+    async fn send_from(&self, proxy: &Proxy<'_>, replaces_id: u32) -> Result<(u32, Option<String>)> {
+        let reply = proxy
+            .call_method(
+                "Notify",
+                &(
+                    &self.app_name,
+                    replaces_id,
+                    &self.icon,
+                    &self.summary,
+                    &self.body,
+                    &self.actions,
+                    &self.hints,
+                    self.expire_timeout,
+                ),
+            )
+            .await
+            .context("failed to send desktop notification")?;
+        let id = reply
+            .body()
+            .deserialize()
+            .context("failed to decode Notify reply")?;
+        let sender = reply.header().sender().map(|sender| sender.to_string());
+        Ok((id, sender))
+    }
+}
+
+pub(crate) struct Pending {
+    id: u32,
+    connection: zbus::Connection,
+    proxy: Proxy<'static>,
+    notification: NotifyArgs,
+    on_server_lost: ServerLostPolicy,
+    /// The server holding the notification, once `Notify` has answered.
+    owner: Option<String>,
+    signal_stream: MessageStream,
+    /// Its signals that arrived before the subscription was pinned to it.
+    early_signals: VecDeque<Message>,
+    owner_stream: NameOwnerChangedStream<'static>,
+}
+
+impl Pending {
+    async fn subscribe(
+        connection: &zbus::Connection,
+        proxy: Proxy<'static>,
+        notification: NotifyArgs,
+        on_server_lost: ServerLostPolicy,
+    ) -> Result<Self> {
+        let dbus = DBusProxy::new(connection)
+            .await
+            .context("failed to create D-Bus proxy")?;
+        let dest = proxy.destination().to_string();
+        let owner_stream = dbus
+            .receive_name_owner_changed_with_args(&[(0, dest.as_str())])
+            .await
+            .context("failed to subscribe to NameOwnerChanged signal")?;
+        let owner = dbus
+            .get_name_owner(proxy.destination().clone())
+            .await
+            .ok()
+            .map(|owner| owner.to_string());
+        let signal_stream = subscribe_signals(connection, owner.as_deref()).await?;
+
+        Ok(Self {
+            id: 0,
+            connection: connection.clone(),
+            proxy,
+            notification,
+            on_server_lost,
+            owner,
+            signal_stream,
+            early_signals: VecDeque::new(),
+            owner_stream,
+        })
+    }
+
+    /// Narrows the signal subscription to the server that answered `Notify`.
+    /// That differs from the owner seen at subscription when the call
+    /// bus-activated the server or the name changed hands in between; its
+    /// signals already received (e.g. an action invoked before the reply)
+    /// are kept, everyone else's are dropped.
+    async fn pin_owner(&mut self, sender: Option<String>) -> Result<()> {
+        let Some(sender) = sender else {
+            return Ok(());
+        };
+        if self.owner.as_deref() == Some(sender.as_str()) {
+            return Ok(());
+        }
+        let pinned = subscribe_signals(&self.connection, Some(&sender)).await?;
+        let mut unpinned = std::mem::replace(&mut self.signal_stream, pinned);
+        while let Some(Some(msg)) = unpinned.next().now_or_never() {
+            let Ok(msg) = msg else {
+                continue;
+            };
+            if msg.header().sender().is_some_and(|name| name.as_str() == sender) {
+                self.early_signals.push_back(msg);
+            }
+        }
+        self.owner = Some(sender);
+        Ok(())
+    }
+
+    pub(crate) async fn wait(&mut self, events: &EventReporter) -> Result<()> {
+        loop {
+            while let Some(msg) = self.early_signals.pop_front() {
+                if self.handle_signal(&msg, events)? {
+                    return Ok(());
+                }
+            }
+            tokio::select! {
+                maybe_msg = self.signal_stream.next() => {
+                    let msg = maybe_msg
+                        .context("notification signal stream ended")?
+                        .context("failed to receive notification signal")?;
+                    if self.handle_signal(&msg, events)? {
+                        return Ok(());
+                    }
+                }
+                maybe_signal = self.owner_stream.next() => {
+                    let signal = maybe_signal.context("NameOwnerChanged signal stream ended")?;
+                    let args = signal.args().context("failed to decode NameOwnerChanged")?;
+                    let old_owner = args.old_owner().as_ref().map(|owner| owner.to_string());
+                    let new_owner = args.new_owner().as_ref().map(|owner| owner.to_string());
+                    if !owner_lost(self.owner.as_deref(), old_owner.as_deref(), new_owner.as_deref()) {
+                        continue;
+                    }
+                    self.owner = new_owner;
+                    self.handle_server_lost(events).await?;
+                }
+            }
+        }
+    }
+
+    /// Reports an action or close of this notification and returns `true`.
+    fn handle_signal(&self, msg: &Message, events: &EventReporter) -> Result<bool> {
+        let header = msg.header();
+        match header.member().map(|member| member.as_str()) {
+            Some("ActionInvoked") => {
+                let (signal_id, action_key): (u32, String) = msg.body().deserialize().context("failed to decode ActionInvoked")?;
+                if signal_id != self.id {
+                    return Ok(false);
+                }
+                // A signed card's actions must echo its nonce; anything else
+                // was not produced from this card.
+                let action_key = match &self.notification.card_nonce {
+                    Some(nonce) => match signing::strip_nonce(&action_key, nonce) {
+                        Some(key) => key.to_string(),
+                        None => {
+                            eprintln!("warning: ignoring action '{action_key}' without this card's nonce");
+                            return Ok(false);
+                        }
+                    },
+                    None => action_key,
+                };
+                events.action(action_key);
+                Ok(true)
+            }
+            Some("NotificationClosed") => {
+                let (signal_id, reason): (u32, u32) = msg.body().deserialize().context("failed to decode NotificationClosed")?;
+                if signal_id != self.id {
+                    return Ok(false);
+                }
+                events.closed(reason);
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    /// The old id died with the previous server, so a resend always asks for a new one.
+    async fn handle_server_lost(&mut self, events: &EventReporter) -> Result<()> {
+        if self.on_server_lost == ServerLostPolicy::Fail {
+            events.event(Event::ServerLost);
+            return Err(ServerLostError {
+                dest: self.proxy.destination().to_string(),
+            }
+            .into());
+        }
+
+        while self.owner.is_none() {
+            let signal = self
+                .owner_stream
+                .next()
+                .await
+                .ok_or_else(|| anyhow!("NameOwnerChanged signal stream ended"))?;
+            let args = signal.args().context("failed to decode NameOwnerChanged")?;
+            self.owner = args.new_owner().as_ref().map(|owner| owner.to_string());
+        }
+
+        self.signal_stream = subscribe_signals(&self.connection, self.owner.as_deref()).await?;
+        self.early_signals.clear();
+        let (id, sender) = self.notification.send_from(&self.proxy, 0).await?;
+        self.id = id;
+        self.pin_owner(sender).await?;
+        events.resent(self.id);
+        Ok(())
+    }
+}
+
+/// Whether a `NameOwnerChanged` loses the notification: the server holding
+/// it gave up the name, or the name has no owner left. Anything else, e.g. the
+/// activation of that very server with none pinned yet, does not.
+fn owner_lost(pinned: Option<&str>, old_owner: Option<&str>, new_owner: Option<&str>) -> bool {
+    (pinned.is_some() && old_owner == pinned) || new_owner.is_none()
+}
+
+/// Whether the server's spec version is 1.2 or later, which introduced
+/// `image-data`. Unparseable versions are assumed to be recent.
+fn supports_image_data(spec_version: &str) -> bool {
+    let mut parts = spec_version.trim().split('.').map(str::parse::<u32>);
+    match (parts.next(), parts.next()) {
+        (Some(Ok(major)), Some(Ok(minor))) => (major, minor) >= (1, 2),
+        (Some(Ok(major)), None) => major >= 2,
+        _ => true,
+    }
+}
+
+/// Byte arrays (e.g. image data) are summarized rather than listed.
+fn variant_to_json(value: &Value<'_>) -> serde_json::Value {
+    match value {
+        Value::U8(value) => json!(value),
+        Value::Bool(value) => json!(value),
+        Value::I16(value) => json!(value),
+        Value::U16(value) => json!(value),
+        Value::I32(value) => json!(value),
+        Value::U32(value) => json!(value),
+        Value::I64(value) => json!(value),
+        Value::U64(value) => json!(value),
+        Value::F64(value) => json!(value),
+        Value::Str(value) => json!(value.as_str()),
+        Value::Signature(value) => json!(value.as_str()),
+        Value::ObjectPath(value) => json!(value.as_str()),
+        Value::Value(value) => json!({
+            "signature": value.value_signature().to_string(),
+            "value": variant_to_json(value),
+        }),
+        Value::Array(array) if array.element_signature() == "y" => {
+            json!(format!("<{} bytes>", array.len()))
+        }
+        Value::Array(array) => array.inner().iter().map(variant_to_json).collect(),
+        Value::Dict(dict) => dict
+            .iter()
+            .map(|(key, value)| {
+                let key = match key {
+                    Value::Str(key) => key.to_string(),
+                    key => variant_to_json(key).to_string(),
+                };
+                (key, variant_to_json(value))
+            })
+            .collect::<serde_json::Map<_, _>>()
+            .into(),
+        Value::Structure(structure) => structure.fields().iter().map(variant_to_json).collect(),
+        Value::Fd(_) => json!("<fd>"),
+    }
+}
+
+/// Subscribes to the daemon's signals only. D-Bus `argN` matching works on
+/// string arguments alone, so the `u32` notification id is compared in `wait`.
+/// Without a known owner (e.g. the daemon is bus-activated by our `Notify`),
+/// the rule matches on path and interface until `Pending::pin_owner`.
+async fn subscribe_signals(
+    connection: &zbus::Connection,
+    owner: Option<&str>,
+) -> Result<MessageStream> {
+    let mut rule = MatchRule::builder()
+        .msg_type(message::Type::Signal)
+        .interface(NOTIFY_IFACE)?
+        .path(NOTIFY_PATH)?;
+    if let Some(owner) = owner {
+        rule = rule.sender(owner)?;
+    }
+    MessageStream::for_match_rule(rule.build(), connection, None)
+        .await
+        .context("failed to subscribe to notification signals")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_the_pinned_server_leaving_is_a_loss() {
+        // Handed over, or gone.
+        assert!(owner_lost(Some(":1.5"), Some(":1.5"), Some(":1.9")));
+        assert!(owner_lost(Some(":1.5"), Some(":1.5"), None));
+        assert!(owner_lost(None, Some(":1.5"), None));
+        // Someone else's change, or an activation.
+        assert!(!owner_lost(Some(":1.5"), Some(":1.7"), Some(":1.9")));
+        assert!(!owner_lost(Some(":1.5"), None, Some(":1.9")));
+        assert!(!owner_lost(None, None, Some(":1.9")));
+        assert!(!owner_lost(None, Some(":1.7"), Some(":1.9")));
+    }
+
+    #[test]
+    fn image_data_needs_spec_1_2() {
+        for version in ["1.2", "1.3", " 1.2 ", "2", "2.0", "unknown", ""] {
+            assert!(supports_image_data(version), "{version}");
+        }
+        for version in ["1.1", "1.0", "0.9", "1"] {
+            assert!(!supports_image_data(version), "{version}");
+        }
+    }
+}
@@ -0,0 +1,164 @@
+//! Timeouts as durations: `500ms`, `30s`, `5m`, `1h30m`, `2d` or bare
+//! milliseconds, plus `never` and `default` (the server's expiry).
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{Error, Result, anyhow, bail};
+use schemars::JsonSchema;
+use serde::Deserialize;
+
+const UNITS: [(&str, u64); 5] = [
+    ("ms", 1),
+    ("s", 1000),
+    ("m", 60 * 1000),
+    ("h", 60 * 60 * 1000),
+    ("d", 24 * 60 * 60 * 1000),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(try_from = "YamlTimeout")]
+#[schemars(with = "YamlTimeout")]
+pub(crate) enum Timeout {
+    /// `default` or -1
+    Default,
+    /// `never` or 0
+    Never,
+    After(u64),
+}
+
+/// duration such as "30s", "5m" or "1h30m", "never", "default", or milliseconds (-1: default, 0: never)
+#[derive(Deserialize, JsonSchema)]
+#[serde(untagged)]
+enum YamlTimeout {
+    Millis(i64),
+    Text(String),
+}
+
+impl TryFrom<YamlTimeout> for Timeout {
+    type Error = Error;
+
+    fn try_from(value: YamlTimeout) -> Result<Self> {
+        match value {
+            YamlTimeout::Millis(millis) => Self::from_millis(millis),
+            YamlTimeout::Text(text) => text.parse(),
+        }
+    }
+}
+
+impl FromStr for Timeout {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let text = text.trim();
+        match text {
+            "default" => Ok(Self::Default),
+            "never" => Ok(Self::Never),
+            _ => match text.parse::<i64>() {
+                Ok(millis) => Self::from_millis(millis),
+                Err(_) => parse_duration(text).map(|millis| match millis {
+                    0 => Self::Never,
+                    millis => Self::After(millis),
+                }),
+            },
+        }
+    }
+}
+
+impl fmt::Display for Timeout {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => formatter.write_str("default"),
+            Self::Never => formatter.write_str("never"),
+            Self::After(millis) => write!(formatter, "{millis}ms"),
+        }
+    }
+}
+
+impl Timeout {
+    fn from_millis(millis: i64) -> Result<Self> {
+        match millis {
+            -1 => Ok(Self::Default),
+            0 => Ok(Self::Never),
+            millis if millis > 0 => Ok(Self::After(millis as u64)),
+            _ => bail!("invalid timeout {millis}; use a duration, 'never', 'default' or -1"),
+        }
+    }
+
+    /// The spec's `expire_timeout`: -1 for the server default, 0 for never.
+    pub(crate) fn expire_timeout(self) -> Result<i32> {
+        match self {
+            Self::Default => Ok(-1),
+            Self::Never => Ok(0),
+            Self::After(millis) => i32::try_from(millis)
+                .map_err(|_| anyhow!("timeout {self} is too long; the longest is {}ms", i32::MAX)),
+        }
+    }
+}
+
+/// Milliseconds in a sequence of `<number><unit>` parts, e.g. `1h30m`.
+pub(crate) fn parse_duration(text: &str) -> Result<u64> {
+    let invalid = || anyhow!("invalid duration '{text}'; use e.g. 500ms, 30s, 5m, 1h30m, never or default");
+    if text.is_empty() {
+        return Err(invalid());
+    }
+    let mut rest = text;
+    let mut total = 0_u64;
+    while !rest.is_empty() {
+        let digits = rest.find(|character: char| !character.is_ascii_digit()).unwrap_or(rest.len());
+        let number = rest[..digits].parse::<u64>().map_err(|_| invalid())?;
+        rest = &rest[digits..];
+        let unit_length = rest.find(|character: char| character.is_ascii_digit()).unwrap_or(rest.len());
+        let (_, scale) = UNITS
+            .iter()
+            .find(|(unit, _)| *unit == &rest[..unit_length])
+            .ok_or_else(invalid)?;
+        rest = &rest[unit_length..];
+        total = number
+            .checked_mul(*scale)
+            .and_then(|millis| total.checked_add(millis))
+            .ok_or_else(invalid)?;
+    }
+    Ok(total)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_add_up_their_parts() {
+        assert_eq!(parse_duration("500ms").unwrap(), 500);
+        assert_eq!(parse_duration("30s").unwrap(), 30_000);
+        assert_eq!(parse_duration("5m").unwrap(), 300_000);
+        assert_eq!(parse_duration("1h30m").unwrap(), 5_400_000);
+        assert_eq!(parse_duration("2d").unwrap(), 172_800_000);
+        assert_eq!(parse_duration("1m500ms").unwrap(), 60_500);
+        assert_eq!(parse_duration("0s").unwrap(), 0);
+    }
+
+    #[test]
+    fn malformed_durations_fail() {
+        for text in ["", "30", "s", "1x", "1.5s", "-1s", "1h 30m", "30S", "18446744073709551615d"] {
+            let error = parse_duration(text).expect_err(text);
+            assert!(error.to_string().starts_with("invalid duration"), "{text}: {error}");
+        }
+    }
+
+    #[test]
+    fn timeouts_from_text_and_millis() {
+        assert_eq!("never".parse::<Timeout>().unwrap(), Timeout::Never);
+        assert_eq!("default".parse::<Timeout>().unwrap(), Timeout::Default);
+        assert_eq!("-1".parse::<Timeout>().unwrap(), Timeout::Default);
+        assert_eq!("0".parse::<Timeout>().unwrap(), Timeout::Never);
+        assert_eq!("0s".parse::<Timeout>().unwrap(), Timeout::Never);
+        assert_eq!("1500".parse::<Timeout>().unwrap(), Timeout::After(1500));
+        assert_eq!(" 2s ".parse::<Timeout>().unwrap(), Timeout::After(2000));
+        assert!("-2".parse::<Timeout>().is_err());
+
+        assert_eq!(Timeout::After(5000).expire_timeout().unwrap(), 5000);
+        assert_eq!(Timeout::Never.expire_timeout().unwrap(), 0);
+        assert_eq!(Timeout::Default.expire_timeout().unwrap(), -1);
+        assert!(Timeout::After(u64::from(u32::MAX)).expire_timeout().is_err());
+    }
+}
@@ -0,0 +1,314 @@
+//! Payload input formats. JSON and TOML payloads are converted into the same
+//! value tree as YAML, so they share the `YamlPayload` model. YAML streams
+//! (`---` separated) and JSON Lines may hold several payloads.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde_yaml::Value;
+
+use crate::validate::{Problem, ensure_valid};
+use crate::yaml_tree::{self, Node, NodeKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum PayloadFormat {
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl PayloadFormat {
+    pub(crate) fn from_path(path: &Path) -> Option<Self> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "yaml" | "yml" => Some(Self::Yaml),
+            "json" => Some(Self::Json),
+            "toml" => Some(Self::Toml),
+            _ => None,
+        }
+    }
+
+    /// Guesses the format from content: `{` starts JSON, a first statement of
+    /// `key = value` or `[table]` is TOML, anything else is YAML.
+    pub(crate) fn sniff(input: &str) -> Self {
+        let trimmed = input.trim_start();
+        if trimmed.starts_with('{') {
+            return Self::Json;
+        }
+
+        let first_statement = input
+            .lines()
+            .map(str::trim)
+            .find(|line| !line.is_empty() && !line.starts_with('#'));
+        match first_statement {
+            Some(line) if line.starts_with('[') && line.ends_with(']') && !line.contains(',') => {
+                Self::Toml
+            }
+            Some(line) => {
+                let is_toml_assignment = line.split_once('=').is_some_and(|(key, _)| {
+                    let key = key.trim();
+                    !key.is_empty()
+                        && !key.contains(':')
+                        && key.chars().all(|character| {
+                            character.is_ascii_alphanumeric()
+                                || matches!(character, '_' | '-' | '.' | '"' | '\'' | ' ')
+                        })
+                });
+                if is_toml_assignment { Self::Toml } else { Self::Yaml }
+            }
+            None => Self::Yaml,
+        }
+    }
+
+    /// `--format` if given, else the file extension, else a guess from content.
+    pub(crate) fn resolve(explicit: Option<Self>, path: Option<&Path>, input: &str) -> Self {
+        explicit
+            .or_else(|| path.and_then(Self::from_path))
+            .unwrap_or_else(|| Self::sniff(input))
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Yaml => "YAML",
+            Self::Json => "JSON",
+            Self::Toml => "TOML",
+        }
+    }
+}
+
+/// Parses every payload in `input`; empty YAML documents are dropped.
+pub(crate) fn parse_payloads(
+    input: &str,
+    format: PayloadFormat,
+    strict: bool,
+) -> Result<Vec<Value>> {
+    let context = || format!("failed to parse {} payload", format.name());
+    match format {
+        PayloadFormat::Yaml => {
+            if strict {
+                let problems = strict_problems(input).with_context(context)?;
+                ensure_valid(&problems, "YAML payload (strict mode)")?;
+            }
+            let mut payloads = Vec::new();
+            for document in serde_yaml::Deserializer::from_str(input) {
+                let payload = Value::deserialize(document).with_context(context)?;
+                if !payload.is_null() {
+                    payloads.push(payload);
+                }
+            }
+            Ok(payloads)
+        }
+        PayloadFormat::Json => serde_json::Deserializer::from_str(input)
+            .into_iter::<Value>()
+            .collect::<Result<_, _>>()
+            .with_context(context),
+        PayloadFormat::Toml => Ok(vec![toml::from_str(input).with_context(context)?]),
+    }
+}
+
+/// Top-level payload fields that are free text.
+const TEXT_FIELDS: [&str; 13] = [
+    "summary",
+    "body",
+    "body_file",
+    "app_name",
+    "icon",
+    "image",
+    "sound",
+    "category",
+    "dest",
+    "bus_address",
+    "sink_file",
+    "signing_key",
+    "deliver_at",
+];
+
+/// Finds unquoted scalars whose meaning depends on the YAML version or that
+/// silently turn text into another type: in text fields (summary, action
+/// labels, card question/choices, ...) any plain scalar that is not a string
+/// under YAML 1.1 or 1.2; in `hints`, plain scalars that YAML 1.1 and 1.2
+/// resolve differently (`yes`, `off`, `0755`, `1_000`, `1:30`, ...).
+pub(crate) fn strict_problems(input: &str) -> Result<Vec<Problem>> {
+    let mut problems = Vec::new();
+    for document in yaml_tree::parse_documents(input)? {
+        let NodeKind::Mapping(entries) = &document.kind else {
+            continue;
+        };
+        for (key, value) in entries {
+            let Some(key) = key.as_str() else { continue };
+            match key {
+                key if TEXT_FIELDS.contains(&key) => check_text(value, key, &mut problems),
+                "actions" => check_labeled_list(value, "actions", &mut problems),
+                "hints" => {
+                    if let NodeKind::Mapping(hints) = &value.kind {
+                        for (hint_key, hint_value) in hints {
+                            let name = format!("hints.{}", hint_key.as_str().unwrap_or_default());
+                            check_version_ambiguous(hint_value, &name, &mut problems);
+                        }
+                    }
+                }
+                "card" => {
+                    for field in ["question", "allow_label"] {
+                        if let Some(node) = value.get(field) {
+                            check_text(node, &format!("card.{field}"), &mut problems);
+                        }
+                    }
+                    if let Some(choices) = value.get("choices") {
+                        check_labeled_list(choices, "card.choices", &mut problems);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(problems)
+}
+
+fn check_labeled_list(node: &Node, name: &str, problems: &mut Vec<Problem>) {
+    let NodeKind::Sequence(items) = &node.kind else {
+        return;
+    };
+    for (index, item) in items.iter().enumerate() {
+        match &item.kind {
+            NodeKind::Mapping(entries) => {
+                for (key, value) in entries {
+                    let key = key.as_str().unwrap_or_default();
+                    check_text(value, &format!("{name}[{index}].{key}"), problems);
+                }
+            }
+            _ => check_text(item, &format!("{name}[{index}]"), problems),
+        }
+    }
+}
+
+fn check_text(node: &Node, name: &str, problems: &mut Vec<Problem>) {
+    if let NodeKind::Scalar { value, plain: true } = &node.kind
+        && resolves_to_non_string(value)
+    {
+        problems.push(Problem::at(
+            node,
+            format!("{name}: unquoted '{value}' is not a string; quote it"),
+        ));
+    }
+}
+
+fn check_version_ambiguous(node: &Node, name: &str, problems: &mut Vec<Problem>) {
+    if let NodeKind::Scalar { value, plain: true } = &node.kind
+        && is_version_ambiguous(value)
+    {
+        problems.push(Problem::at(
+            node,
+            format!("{name}: unquoted '{value}' means different things in YAML 1.1 and 1.2; quote it or use an explicit value"),
+        ));
+    }
+}
+
+fn resolves_to_non_string(value: &str) -> bool {
+    is_version_ambiguous(value)
+        || matches!(
+            value,
+            "" | "~" | "null" | "Null" | "NULL" | "true" | "True" | "TRUE" | "false" | "False"
+                | "FALSE"
+        )
+        || is_decimal_number(value)
+        || is_special_float(value)
+        || has_radix_prefix(value)
+}
+
+/// Plain scalars that YAML 1.1 resolves to a bool or number but YAML 1.2 does
+/// not (or resolves differently).
+fn is_version_ambiguous(value: &str) -> bool {
+    if matches!(
+        value,
+        "y" | "Y" | "yes" | "Yes" | "YES" | "n" | "N" | "no" | "No" | "NO" | "on" | "On" | "ON"
+            | "off" | "Off" | "OFF"
+    ) {
+        return true;
+    }
+
+    let unsigned = value.trim_start_matches(['+', '-']);
+    let is_digits = |text: &str| !text.is_empty() && text.chars().all(|c| c.is_ascii_digit());
+    // YAML 1.1 octal (`0755`) vs YAML 1.2 decimal.
+    if unsigned.len() > 1 && unsigned.starts_with('0') && is_digits(unsigned) {
+        return true;
+    }
+    // YAML 1.1 digit separators (`1_000`).
+    if unsigned.contains('_')
+        && unsigned
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '_' || c == '.')
+        && unsigned.starts_with(|c: char| c.is_ascii_digit())
+    {
+        return true;
+    }
+    // YAML 1.1 sexagesimal (`1:30`).
+    let mut parts = unsigned.split(':');
+    let first = parts.next().unwrap_or_default();
+    let rest = parts.collect::<Vec<_>>();
+    !rest.is_empty()
+        && is_digits(first)
+        && rest.iter().all(|part| {
+            let part = part.split_once('.').map_or(*part, |(whole, _)| whole);
+            is_digits(part) && part.len() <= 2
+        })
+}
+
+/// YAML 1.2 core integers and floats plus YAML 1.1 floats with `_`, e.g. `7`,
+/// `-1.5`, `.5`, `1e5` or `1_000.5`. Rust-only forms such as `inf`, `nan` and
+/// `infinity` are strings in YAML.
+fn is_decimal_number(value: &str) -> bool {
+    let unsigned = value.strip_prefix(['+', '-']).unwrap_or(value);
+    let (mantissa, exponent) = match unsigned.split_once(['e', 'E']) {
+        Some((mantissa, exponent)) => (mantissa, Some(exponent)),
+        None => (unsigned, None),
+    };
+    let exponent_valid = exponent.is_none_or(|exponent| {
+        let digits = exponent.strip_prefix(['+', '-']).unwrap_or(exponent);
+        !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+    });
+    let is_digits = |text: &str| text.chars().all(|c| c.is_ascii_digit() || c == '_');
+    let starts_with_digit = |text: &str| text.starts_with(|c: char| c.is_ascii_digit());
+    let mantissa_valid = match mantissa.split_once('.') {
+        Some((whole, fraction)) => {
+            is_digits(whole)
+                && is_digits(fraction)
+                && (starts_with_digit(whole) || (whole.is_empty() && starts_with_digit(fraction)))
+        }
+        None => starts_with_digit(mantissa) && is_digits(mantissa),
+    };
+    mantissa_valid && exponent_valid
+}
+
+fn is_special_float(value: &str) -> bool {
+    let unsigned = value.trim_start_matches(['+', '-']);
+    matches!(
+        unsigned,
+        ".inf" | ".Inf" | ".INF" | ".nan" | ".NaN" | ".NAN"
+    )
+}
+
+fn has_radix_prefix(value: &str) -> bool {
+    let unsigned = value.trim_start_matches(['+', '-']);
+    ["0x", "0o", "0b"].iter().any(|prefix| {
+        unsigned
+            .strip_prefix(prefix)
+            .is_some_and(|digits| !digits.is_empty() && digits.chars().all(|c| c.is_ascii_hexdigit() || c == '_'))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_follow_the_yaml_grammars() {
+        for number in ["7", "-7", "+1.5", "1.", ".5", "1e5", "6.02E+23", "1.5e-3", "1_000.5", "0x1F", ".inf", "-.NaN"] {
+            assert!(resolves_to_non_string(number), "{number}");
+        }
+        for text in ["inf", "-infinity", "nan", "NaN", "e5", "1e", "1.2.3", ".", "-", "._5", "1,5", "0x"] {
+            assert!(!resolves_to_non_string(text), "{text}");
+        }
+    }
+}
@@ -0,0 +1,40 @@
+//! Lowercase hex encoding for spooled hints, signatures and ids.
+
+pub(crate) fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// `None` unless `text` is an even number of hex digits.
+pub(crate) fn decode(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) || !text.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&text[index..index + 2], 16).ok())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips() {
+        let bytes = [0x00, 0x7f, 0x80, 0xff];
+        assert_eq!(encode(&bytes), "007f80ff");
+        assert_eq!(decode("007f80ff"), Some(bytes.to_vec()));
+        assert_eq!(decode("007F80FF"), Some(bytes.to_vec()));
+        assert_eq!(decode(""), Some(Vec::new()));
+    }
+
+    #[test]
+    fn rejects_malformed_input() {
+        assert_eq!(decode("abc"), None);
+        assert_eq!(decode("zz"), None);
+        assert_eq!(decode("+1"), None);
+        // Multi-byte characters must not split a pair and panic.
+        assert_eq!(decode("aé"), None);
+        assert_eq!(decode("0é0"), None);
+    }
+}
@@ -0,0 +1,639 @@
+//! Notification hints: typed values from `--hint [TYPE:]KEY:VALUE` and YAML
+//! `hints`, with the spec's well-known hints checked against their D-Bus types.
+
+use std::fs::File;
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow, bail};
+use schemars::JsonSchema;
+use serde::Deserialize;
+use zvariant::{Array, Dict, OwnedValue, Signature, Str, Value};
+
+use crate::{Urgency, sanitize_text};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum HintType {
+    Boolean,
+    Byte,
+    Int16,
+    Uint16,
+    Int32,
+    Uint32,
+    Int64,
+    Uint64,
+    Double,
+    String,
+}
+
+/// Type names: notify-send's first, then explicit widths.
+const TYPE_NAMES: [(&str, HintType); 13] = [
+    ("boolean", HintType::Boolean),
+    ("byte", HintType::Byte),
+    ("int", HintType::Int32),
+    ("double", HintType::Double),
+    ("string", HintType::String),
+    ("bool", HintType::Boolean),
+    ("uint8", HintType::Byte),
+    ("int16", HintType::Int16),
+    ("uint16", HintType::Uint16),
+    ("int32", HintType::Int32),
+    ("uint32", HintType::Uint32),
+    ("int64", HintType::Int64),
+    ("uint64", HintType::Uint64),
+];
+
+/// Hints whose type the notification spec fixes. Untyped values for these
+/// keys are converted to the required type; explicitly typed ones must match.
+const WELL_KNOWN: [(&str, HintType); 14] = [
+    ("action-icons", HintType::Boolean),
+    ("category", HintType::String),
+    ("desktop-entry", HintType::String),
+    ("image-path", HintType::String),
+    ("image_path", HintType::String),
+    ("resident", HintType::Boolean),
+    ("sound-file", HintType::String),
+    ("sound-name", HintType::String),
+    ("suppress-sound", HintType::Boolean),
+    ("transient", HintType::Boolean),
+    ("urgency", HintType::Byte),
+    ("x", HintType::Int32),
+    ("y", HintType::Int32),
+    ("value", HintType::Int32),
+];
+
+/// Raw image hints; they are structures (`(iiibiiay)`) that cannot be written
+/// as hint values.
+const IMAGE_HINTS: [&str; 3] = ["image-data", "image_data", "icon_data"];
+
+impl HintType {
+    fn parse(name: &str) -> Option<Self> {
+        TYPE_NAMES
+            .iter()
+            .find(|(candidate, _)| candidate.eq_ignore_ascii_case(name))
+            .map(|(_, kind)| *kind)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Boolean => "boolean",
+            Self::Byte => "byte",
+            Self::Int16 => "int16",
+            Self::Uint16 => "uint16",
+            Self::Int32 => "int32",
+            Self::Uint32 => "uint32",
+            Self::Int64 => "int64",
+            Self::Uint64 => "uint64",
+            Self::Double => "double",
+            Self::String => "string",
+        }
+    }
+
+    fn signature(self) -> &'static str {
+        match self {
+            Self::Boolean => "b",
+            Self::Byte => "y",
+            Self::Int16 => "n",
+            Self::Uint16 => "q",
+            Self::Int32 => "i",
+            Self::Uint32 => "u",
+            Self::Int64 => "x",
+            Self::Uint64 => "t",
+            Self::Double => "d",
+            Self::String => "s",
+        }
+    }
+
+    fn parse_text(self, text: &str) -> Result<Value<'static>> {
+        let invalid = || anyhow!("'{text}' is not a valid {}", self.name());
+        Ok(match self {
+            Self::Boolean => match text.to_ascii_lowercase().as_str() {
+                "true" | "1" => Value::Bool(true),
+                "false" | "0" => Value::Bool(false),
+                _ => return Err(invalid()),
+            },
+            Self::Byte => Value::U8(text.parse().map_err(|_| invalid())?),
+            Self::Int16 => Value::I16(text.parse().map_err(|_| invalid())?),
+            Self::Uint16 => Value::U16(text.parse().map_err(|_| invalid())?),
+            Self::Int32 => Value::I32(text.parse().map_err(|_| invalid())?),
+            Self::Uint32 => Value::U32(text.parse().map_err(|_| invalid())?),
+            Self::Int64 => Value::I64(text.parse().map_err(|_| invalid())?),
+            Self::Uint64 => Value::U64(text.parse().map_err(|_| invalid())?),
+            Self::Double => Value::F64(text.parse().map_err(|_| invalid())?),
+            Self::String => Value::from(sanitize_text(text.to_string())),
+        })
+    }
+
+    fn convert_yaml(self, value: &serde_yaml::Value) -> Result<Value<'static>> {
+        match value {
+            serde_yaml::Value::String(text) => self.parse_text(text),
+            serde_yaml::Value::Bool(flag) if self == Self::Boolean => Ok(Value::Bool(*flag)),
+            serde_yaml::Value::Bool(flag) if self == Self::String => Ok(Value::from(flag.to_string())),
+            serde_yaml::Value::Number(number) => self.parse_text(&number.to_string()),
+            _ => bail!("expected a {} value", self.name()),
+        }
+    }
+}
+
+/// An array or dict element type; `variant` elements keep the guessed type of each value.
+#[derive(Debug, Clone, Copy)]
+enum Element {
+    Typed(HintType),
+    Variant,
+}
+
+impl Element {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "variant" => Some(Self::Variant),
+            name => HintType::parse(name).map(Self::Typed),
+        }
+    }
+
+    fn signature(self) -> &'static str {
+        match self {
+            Self::Typed(kind) => kind.signature(),
+            Self::Variant => "v",
+        }
+    }
+
+    fn parse_text(self, text: &str) -> Result<Value<'static>> {
+        match self {
+            Self::Typed(kind) => kind.parse_text(text),
+            Self::Variant => Ok(Value::Value(Box::new(guess_from_str(text)))),
+        }
+    }
+
+    fn convert_yaml(self, value: &serde_yaml::Value) -> Result<Value<'static>> {
+        match self {
+            Self::Typed(kind) => kind.convert_yaml(value),
+            Self::Variant => Ok(Value::Value(Box::new(guess_from_yaml(value)?))),
+        }
+    }
+}
+
+/// A hint type: a scalar, `array[:ELEMENT]` or `dict[:ELEMENT]` (string keys).
+#[derive(Debug, Clone, Copy)]
+enum Spec {
+    Scalar(HintType),
+    Array(Element),
+    Dict(Element),
+}
+
+impl Spec {
+    fn parse(spec: &str) -> Result<Self> {
+        let parsed = match spec.split_once(':') {
+            Some(("array", element)) => Element::parse(element).map(Self::Array),
+            Some(("dict", element)) => Element::parse(element).map(Self::Dict),
+            Some(_) => None,
+            None if spec == "array" => Some(Self::Array(Element::Variant)),
+            None if spec == "dict" => Some(Self::Dict(Element::Variant)),
+            None => HintType::parse(spec).map(Self::Scalar),
+        };
+        parsed.ok_or_else(|| {
+            anyhow!(
+                "unknown hint type '{spec}' (expected boolean, byte, int, int16, uint16, int32, uint32, int64, uint64, double, string, array:TYPE or dict:TYPE)"
+            )
+        })
+    }
+
+    /// CLI values: arrays are comma-separated, dicts are `key=value` pairs.
+    fn parse_text(self, text: &str) -> Result<Value<'static>> {
+        match self {
+            Self::Scalar(kind) => kind.parse_text(text),
+            Self::Array(element) => {
+                let items = split_list(text);
+                build_array(element, items.into_iter().map(|item| element.parse_text(item)))
+            }
+            Self::Dict(element) => {
+                let mut entries = Vec::new();
+                for item in split_list(text) {
+                    let (key, value) = item
+                        .split_once('=')
+                        .ok_or_else(|| anyhow!("dict entry '{item}' must be key=value"))?;
+                    entries.push((key.trim().to_string(), element.parse_text(value.trim())?));
+                }
+                build_dict(element, entries)
+            }
+        }
+    }
+
+    fn convert_yaml(self, value: &serde_yaml::Value) -> Result<Value<'static>> {
+        match (self, value) {
+            (Self::Scalar(kind), value) => kind.convert_yaml(value),
+            (Self::Array(element), serde_yaml::Value::Sequence(items)) => {
+                build_array(element, items.iter().map(|item| element.convert_yaml(item)))
+            }
+            (Self::Dict(element), serde_yaml::Value::Mapping(mapping)) => {
+                let mut entries = Vec::new();
+                for (key, value) in mapping {
+                    let key = key
+                        .as_str()
+                        .ok_or_else(|| anyhow!("dict hint keys must be strings"))?;
+                    entries.push((key.to_string(), element.convert_yaml(value)?));
+                }
+                build_dict(element, entries)
+            }
+            (Self::Array(_), _) => bail!("array hints need a list value"),
+            (Self::Dict(_), _) => bail!("dict hints need a mapping value"),
+        }
+    }
+}
+
+fn split_list(text: &str) -> Vec<&str> {
+    if text.trim().is_empty() {
+        return Vec::new();
+    }
+    text.split(',').map(str::trim).collect()
+}
+
+fn build_array(
+    element: Element,
+    items: impl Iterator<Item = Result<Value<'static>>>,
+) -> Result<Value<'static>> {
+    let mut array = Array::new(Signature::from_static_str_unchecked(element.signature()));
+    for item in items {
+        array.append(item?).context("failed to build array hint")?;
+    }
+    Ok(Value::Array(array))
+}
+
+fn build_dict(element: Element, entries: Vec<(String, Value<'static>)>) -> Result<Value<'static>> {
+    let mut dict = Dict::new(
+        Signature::from_static_str_unchecked("s"),
+        Signature::from_static_str_unchecked(element.signature()),
+    );
+    for (key, value) in entries {
+        dict.append(Value::from(sanitize_text(key)), value)
+            .context("failed to build dict hint")?;
+    }
+    Ok(Value::Dict(dict))
+}
+
+/// A YAML hint: a plain scalar (type guessed, or fixed for well-known hints)
+/// or `{type: uint32, value: 5}`.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(untagged, deny_unknown_fields)]
+pub(crate) enum YamlHint {
+    Typed {
+        /// boolean, byte, int16, uint16, int32, uint32, int64, uint64, double, string, array:TYPE or dict:TYPE
+        #[serde(rename = "type")]
+        kind: String,
+        #[schemars(with = "serde_json::Value")]
+        value: serde_yaml::Value,
+    },
+    Plain(#[schemars(with = "serde_json::Value")] serde_yaml::Value),
+}
+
+pub(crate) fn from_yaml(key: &str, hint: &YamlHint) -> Result<OwnedValue> {
+    let value = match hint {
+        YamlHint::Typed { kind, value } => {
+            let value = Spec::parse(kind)?.convert_yaml(value)?;
+            check_well_known(key, &value)?;
+            value
+        }
+        YamlHint::Plain(value) => match well_known_type(key)? {
+            Some(kind) => {
+                let value = kind.convert_yaml(value)?;
+                check_well_known(key, &value)?;
+                value
+            }
+            None => guess_from_yaml(value)?,
+        },
+    };
+    into_owned(value).with_context(|| format!("invalid hint '{key}'"))
+}
+
+/// `--hint KEY:VALUE`, `--hint TYPE:KEY:VALUE` or `--hint array:TYPE:KEY:A,B`.
+pub(crate) fn parse_cli(input: &str) -> Result<(String, OwnedValue)> {
+    let invalid = |reason: &str| anyhow!("invalid --hint '{input}', {reason}");
+
+    let typed = match input.splitn(4, ':').collect::<Vec<_>>().as_slice() {
+        [container @ ("array" | "dict"), element, key, value] => {
+            Some((format!("{container}:{element}"), *key, *value))
+        }
+        _ => match input.splitn(3, ':').collect::<Vec<_>>().as_slice() {
+            [kind, key, value] if HintType::parse(kind).is_some() => {
+                Some((kind.to_string(), *key, *value))
+            }
+            _ => None,
+        },
+    };
+
+    let (key, value) = match typed {
+        Some((spec, key, value)) => {
+            let key = key.trim();
+            if key.is_empty() {
+                return Err(invalid("key cannot be empty"));
+            }
+            let spec = Spec::parse(&spec).map_err(|error| invalid(&error.to_string()))?;
+            let value = spec
+                .parse_text(value.trim())
+                .map_err(|error| invalid(&format!("{error:#}")))?;
+            check_well_known(key, &value).map_err(|error| invalid(&error.to_string()))?;
+            (key, value)
+        }
+        None => {
+            let (key, value) = input
+                .split_once(':')
+                .ok_or_else(|| invalid("expected KEY:VALUE or TYPE:KEY:VALUE"))?;
+            let key = key.trim();
+            if key.is_empty() {
+                return Err(invalid("key cannot be empty"));
+            }
+            let value = match well_known_type(key).map_err(|error| invalid(&error.to_string()))? {
+                Some(kind) => {
+                    let value = kind
+                        .parse_text(value.trim())
+                        .map_err(|error| invalid(&format!("{error:#}")))?;
+                    check_well_known(key, &value).map_err(|error| invalid(&error.to_string()))?;
+                    value
+                }
+                None => guess_from_str(value.trim()),
+            };
+            (key, value)
+        }
+    };
+
+    let value = into_owned(value).map_err(|error| invalid(&format!("{error:#}")))?;
+    Ok((key.to_string(), value))
+}
+
+/// Default sounds per urgency, used when no `sound` is set.
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct UrgencySounds {
+    low: Option<String>,
+    normal: Option<String>,
+    critical: Option<String>,
+}
+
+impl UrgencySounds {
+    pub(crate) fn get(&self, urgency: Urgency) -> Option<&str> {
+        match urgency {
+            Urgency::Low => self.low.as_deref(),
+            Urgency::Normal => self.normal.as_deref(),
+            Urgency::Critical => self.critical.as_deref(),
+        }
+    }
+
+    /// Takes out the sounds that are files, leaving the themed names.
+    pub(crate) fn take_files(&mut self) -> Vec<String> {
+        [&mut self.low, &mut self.normal, &mut self.critical]
+            .into_iter()
+            .filter_map(|sound| sound.take_if(|sound| sound_path(sound).is_some()))
+            .collect()
+    }
+}
+
+/// The file a `sound` names: a path (containing `/` or with a file extension,
+/// or a `file://` URI), as opposed to a themed sound name.
+pub(crate) fn sound_path(sound: &str) -> Option<&str> {
+    let sound = sound.trim();
+    let path = sound.strip_prefix("file://").unwrap_or(sound);
+    (path.contains('/') || Path::new(path).extension().is_some()).then_some(path)
+}
+
+/// `--sound`: a path becomes `sound-file`, anything else a themed `sound-name`.
+pub(crate) fn sound(sound: &str) -> Result<(String, OwnedValue)> {
+    let sound = sound.trim();
+    if sound.is_empty() {
+        bail!("sound cannot be empty");
+    }
+    let Some(path) = sound_path(sound) else {
+        return Ok(("sound-name".to_string(), OwnedValue::from(Str::from(sanitize_text(sound.to_string())))));
+    };
+
+    let path = std::path::absolute(path).with_context(|| format!("failed to resolve sound file path: {path}"))?;
+    let path = path.to_string_lossy().into_owned();
+    check_sound_file(&path)?;
+    Ok(("sound-file".to_string(), OwnedValue::from(Str::from(path))))
+}
+
+/// The daemon plays `sound-file` itself, so a missing or unreadable file
+/// would otherwise fail silently.
+pub(crate) fn check_sound_file(path: &str) -> Result<()> {
+    let path = path.strip_prefix("file://").unwrap_or(path);
+    let file = File::open(path).with_context(|| format!("cannot read sound file: {path}"))?;
+    let metadata = file
+        .metadata()
+        .with_context(|| format!("cannot read sound file: {path}"))?;
+    if !metadata.is_file() {
+        bail!("sound file is not a regular file: {path}");
+    }
+    Ok(())
+}
+
+fn well_known_type(key: &str) -> Result<Option<HintType>> {
+    if IMAGE_HINTS.contains(&key) {
+        bail!("hint '{key}' takes raw image data (iiibiiay) and cannot be set as a hint value");
+    }
+    Ok(WELL_KNOWN
+        .iter()
+        .find(|(name, _)| *name == key)
+        .map(|(_, kind)| *kind))
+}
+
+fn check_well_known(key: &str, value: &Value<'_>) -> Result<()> {
+    let Some(required) = well_known_type(key)? else {
+        return Ok(());
+    };
+    let signature = value.value_signature();
+    if signature != required.signature() {
+        bail!(
+            "hint '{key}' must be {} ({}), not '{signature}'",
+            required.name(),
+            required.signature()
+        );
+    }
+    if key == "urgency" && !matches!(value, Value::U8(0..=2)) {
+        bail!("hint 'urgency' must be 0 (low), 1 (normal) or 2 (critical)");
+    }
+    Ok(())
+}
+
+fn into_owned(value: Value<'static>) -> Result<OwnedValue> {
+    OwnedValue::try_from(value).context("failed to convert hint value")
+}
+
+/// Untyped CLI values: booleans, then integers (int64), then doubles, else strings.
+fn guess_from_str(value: &str) -> Value<'static> {
+    if value.eq_ignore_ascii_case("true") {
+        return Value::Bool(true);
+    }
+    if value.eq_ignore_ascii_case("false") {
+        return Value::Bool(false);
+    }
+    if let Ok(number) = value.parse::<i64>() {
+        return Value::I64(number);
+    }
+    if let Ok(number) = value.parse::<f64>() {
+        return Value::F64(number);
+    }
+    Value::Str(Str::from(sanitize_text(value.to_string())))
+}
+
+fn guess_from_yaml(value: &serde_yaml::Value) -> Result<Value<'static>> {
+    match value {
+        serde_yaml::Value::Bool(v) => Ok(Value::Bool(*v)),
+        serde_yaml::Value::Number(v) => {
+            if let Some(i) = v.as_i64() {
+                Ok(Value::I64(i))
+            } else if let Some(u) = v.as_u64() {
+                Ok(Value::U64(u))
+            } else if let Some(f) = v.as_f64() {
+                Ok(Value::F64(f))
+            } else {
+                Err(anyhow!("unsupported numeric hint value"))
+            }
+        }
+        serde_yaml::Value::String(v) => Ok(Value::from(sanitize_text(v.clone()))),
+        serde_yaml::Value::Null => Ok(Value::from("")),
+        _ => Err(anyhow!(
+            "unsupported YAML hint type; use a scalar or {{type: ..., value: ...}}"
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+
+    fn cli(input: &str) -> (String, OwnedValue) {
+        parse_cli(input).unwrap()
+    }
+
+    fn cli_error(input: &str) -> String {
+        format!("{:#}", parse_cli(input).expect_err(input))
+    }
+
+    fn yaml(key: &str, hint: &str) -> Result<OwnedValue> {
+        from_yaml(key, &serde_yaml::from_str::<YamlHint>(hint).unwrap())
+    }
+
+    #[test]
+    fn untyped_cli_values_are_guessed() {
+        assert_eq!(cli("count:5"), ("count".to_string(), OwnedValue::from(5_i64)));
+        assert_eq!(*cli("offset:-7").1, Value::I64(-7));
+        assert_eq!(*cli("ratio:1.5").1, Value::F64(1.5));
+        assert_eq!(*cli("flag:TRUE").1, Value::Bool(true));
+        assert_eq!(*cli("name: spaced value ").1, Value::from("spaced value"));
+    }
+
+    #[test]
+    fn ambiguous_cli_input_only_types_known_type_names() {
+        // A type name needs a key after it.
+        assert_eq!(cli("int:5"), ("int".to_string(), OwnedValue::from(5_i64)));
+        // Not a type name: the rest, colons included, is the value.
+        assert_eq!(*cli("x-url:https://example.org").1, Value::from("https://example.org"));
+        assert_eq!(*cli("string:x-url:https://example.org").1, Value::from("https://example.org"));
+        assert_eq!(*cli("category:int:5").1, Value::from("int:5"));
+        assert_eq!(*cli("UINT32:count:5").1, Value::U32(5));
+        assert_eq!(*cli("double:ratio:2").1, Value::F64(2.0));
+    }
+
+    #[test]
+    fn well_known_cli_hints_get_their_type() {
+        assert_eq!(*cli("x:10").1, Value::I32(10));
+        assert_eq!(*cli("int:y:-3").1, Value::I32(-3));
+        assert_eq!(*cli("urgency:2").1, Value::U8(2));
+        assert_eq!(*cli("byte:urgency:0").1, Value::U8(0));
+        assert_eq!(*cli("transient:1").1, Value::Bool(true));
+        assert_eq!(*cli("category:5").1, Value::from("5"));
+
+        assert!(cli_error("int64:x:5").contains("hint 'x' must be int32 (i), not 'x'"));
+        assert!(cli_error("x:3000000000").contains("not a valid int32"));
+        assert!(cli_error("urgency:3").contains("must be 0 (low), 1 (normal) or 2 (critical)"));
+        assert!(cli_error("uint32:urgency:1").contains("must be byte (y)"));
+        assert!(cli_error("resident:maybe").contains("not a valid boolean"));
+        assert!(cli_error("image-data:abc").contains("cannot be set as a hint value"));
+    }
+
+    #[test]
+    fn malformed_cli_hints_fail() {
+        assert!(cli_error("novalue").contains("expected KEY:VALUE"));
+        assert!(cli_error(":5").contains("key cannot be empty"));
+        assert!(cli_error("int: :5").contains("key cannot be empty"));
+        assert!(cli_error("byte:level:256").contains("not a valid byte"));
+        assert!(cli_error("array:nope:ids:1").contains("unknown hint type 'array:nope'"));
+        assert!(cli_error("dict:string:meta:a").contains("dict entry 'a' must be key=value"));
+    }
+
+    #[test]
+    fn cli_arrays_and_dicts() {
+        let (key, value) = cli("array:int32:ids:1, 2,3");
+        assert_eq!(key, "ids");
+        assert_eq!(value.value_signature(), "ai");
+        assert_eq!(Vec::<i32>::try_from(value).unwrap(), [1, 2, 3]);
+
+        let (_, value) = cli("array:string:tags:");
+        assert_eq!(Vec::<String>::try_from(value).unwrap(), Vec::<String>::new());
+
+        let (_, value) = cli("array:variant:mixed:1,two,true");
+        assert_eq!(value.value_signature(), "av");
+
+        let (_, value) = cli("dict:string:meta:a=1, b = two");
+        assert_eq!(value.value_signature(), "a{ss}");
+        let meta = HashMap::<String, String>::try_from(value).unwrap();
+        assert_eq!(meta["a"], "1");
+        assert_eq!(meta["b"], "two");
+    }
+
+    #[test]
+    fn yaml_plain_values() {
+        assert_eq!(*yaml("count", "5").unwrap(), Value::I64(5));
+        assert_eq!(*yaml("big", "18446744073709551615").unwrap(), Value::U64(u64::MAX));
+        assert_eq!(*yaml("ratio", "0.5").unwrap(), Value::F64(0.5));
+        assert_eq!(*yaml("flag", "false").unwrap(), Value::Bool(false));
+        assert_eq!(*yaml("empty", "~").unwrap(), Value::from(""));
+        assert!(format!("{:#}", yaml("list", "[1, 2]").unwrap_err()).contains("unsupported YAML hint type"));
+
+        // Well-known hints are converted, from numbers or text.
+        assert_eq!(*yaml("x", "5").unwrap(), Value::I32(5));
+        assert_eq!(*yaml("y", "'7'").unwrap(), Value::I32(7));
+        assert_eq!(*yaml("urgency", "1").unwrap(), Value::U8(1));
+        assert_eq!(*yaml("transient", "true").unwrap(), Value::Bool(true));
+        assert!(yaml("urgency", "5").is_err());
+        assert!(yaml("value", "true").is_err());
+    }
+
+    #[test]
+    fn yaml_typed_values() {
+        assert_eq!(*yaml("count", "{type: uint32, value: 5}").unwrap(), Value::U32(5));
+        assert_eq!(*yaml("label", "{type: string, value: true}").unwrap(), Value::from("true"));
+        assert_eq!(*yaml("x", "{type: int, value: '-4'}").unwrap(), Value::I32(-4));
+        assert!(yaml("x", "{type: uint32, value: 4}").is_err());
+        assert!(yaml("flag", "{type: boolean, value: [true]}").is_err());
+
+        let ids = yaml("ids", "{type: 'array:uint16', value: [1, 2]}").unwrap();
+        assert_eq!(Vec::<u16>::try_from(ids).unwrap(), [1, 2]);
+        let meta = yaml("meta", "{type: 'dict:int32', value: {a: 1}}").unwrap();
+        assert_eq!(HashMap::<String, i32>::try_from(meta).unwrap()["a"], 1);
+        let mixed = yaml("mixed", "{type: array, value: [1, a]}").unwrap();
+        assert_eq!(mixed.value_signature(), "av");
+
+        assert!(format!("{:#}", yaml("ids", "{type: 'array:int32', value: 5}").unwrap_err()).contains("need a list"));
+        assert!(format!("{:#}", yaml("meta", "{type: dict, value: [1]}").unwrap_err()).contains("need a mapping"));
+        assert!(format!("{:#}", yaml("k", "{type: float, value: 1}").unwrap_err()).contains("unknown hint type 'float'"));
+    }
+
+    #[test]
+    fn sounds_are_names_or_readable_files() {
+        let (key, value) = sound("message-new-instant").unwrap();
+        assert_eq!((key.as_str(), &*value), ("sound-name", &Value::from("message-new-instant")));
+        assert!(sound("  ").is_err());
+        assert_eq!(sound_path("bell.oga"), Some("bell.oga"));
+        assert_eq!(sound_path(" file://sounds/bell "), Some("sounds/bell"));
+        assert_eq!(sound_path("message-new-instant"), None);
+
+        let path = std::env::temp_dir().join(format!("notify-test-{}-bell.oga", std::process::id()));
+        std::fs::write(&path, b"").unwrap();
+        let (key, value) = sound(&format!("file://{}", path.display())).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!((key.as_str(), &*value), ("sound-file", &Value::from(path.to_str().unwrap())));
+
+        assert!(format!("{:#}", sound("/nonexistent/bell.oga").unwrap_err()).contains("cannot read sound file"));
+        assert!(format!("{:#}", sound("/").unwrap_err()).contains("not a regular file"));
+    }
+}
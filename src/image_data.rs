@@ -0,0 +1,143 @@
+//! `--image`: loads a PNG, JPEG or SVG file into the spec's `image-data` hint,
+//! a `(iiibiiay)` structure of width, height, rowstride, has_alpha,
+//! bits_per_sample, channels and the raw pixel rows.
+
+use std::borrow::Cow;
+use std::path::Path;
+
+use anyhow::{Context, Result, anyhow, bail};
+use image::DynamicImage;
+use image::imageops::FilterType;
+use resvg::{tiny_skia, usvg};
+use zvariant::{OwnedValue, Value};
+
+/// Reads `path` (for `-`, the image already read from stdin) and scales it
+/// down to fit `max_size` pixels on its longer side, if given.
+pub(crate) fn load(path: &Path, stdin_image: Option<&[u8]>, max_size: Option<u32>) -> Result<OwnedValue> {
+    if max_size == Some(0) {
+        bail!("image size must be at least 1 pixel");
+    }
+
+    let bytes = if path.as_os_str() == "-" {
+        Cow::Borrowed(stdin_image.ok_or_else(|| anyhow!("an image from stdin needs --image -"))?)
+    } else {
+        Cow::Owned(
+            std::fs::read(path)
+                .with_context(|| format!("failed to read image file: {}", path.display()))?,
+        )
+    };
+
+    let is_svg = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("svg"))
+        || bytes.trim_ascii_start().starts_with(b"<");
+    let image = if is_svg {
+        render_svg(&bytes, max_size)
+    } else {
+        decode_raster(&bytes, max_size)
+    }
+    .with_context(|| format!("failed to load image: {}", path.display()))?;
+
+    to_hint(image)
+}
+
+fn decode_raster(bytes: &[u8], max_size: Option<u32>) -> Result<DynamicImage> {
+    let image = image::load_from_memory(bytes).context("unsupported or corrupt image (expected PNG, JPEG or SVG)")?;
+    Ok(match max_size {
+        Some(max) if image.width() > max || image.height() > max => {
+            image.resize(max, max, FilterType::Lanczos3)
+        }
+        _ => image,
+    })
+}
+
+/// Longest side an SVG is rendered at; its declared size can be anything.
+const MAX_SVG_SIZE: u32 = 1024;
+
+/// SVGs are rendered at their own size, scaled down to fit `max_size` and
+/// `MAX_SVG_SIZE`.
+fn render_svg(bytes: &[u8], max_size: Option<u32>) -> Result<DynamicImage> {
+    let tree = usvg::Tree::from_data(bytes, &usvg::Options::default()).context("invalid SVG")?;
+    let size = tree.size();
+    let max = max_size.map_or(MAX_SVG_SIZE, |max| max.min(MAX_SVG_SIZE));
+    let scale = (max as f32 / size.width().max(size.height())).min(1.0);
+    let width = (size.width() * scale).round().max(1.0) as u32;
+    let height = (size.height() * scale).round().max(1.0) as u32;
+
+    let mut pixmap = tiny_skia::Pixmap::new(width, height)
+        .ok_or_else(|| anyhow!("SVG size {width}x{height} is not renderable"))?;
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+
+    // tiny-skia keeps premultiplied alpha; the hint expects straight RGBA.
+    let pixels = pixmap
+        .pixels()
+        .iter()
+        .flat_map(|pixel| {
+            let color = pixel.demultiply();
+            [color.red(), color.green(), color.blue(), color.alpha()]
+        })
+        .collect();
+    let buffer = image::RgbaImage::from_raw(width, height, pixels)
+        .ok_or_else(|| anyhow!("SVG render produced a short buffer"))?;
+    Ok(DynamicImage::ImageRgba8(buffer))
+}
+
+fn to_hint(image: DynamicImage) -> Result<OwnedValue> {
+    let has_alpha = image.color().has_alpha();
+    let (width, height, channels, data) = if has_alpha {
+        let image = image.into_rgba8();
+        (image.width(), image.height(), 4, image.into_raw())
+    } else {
+        let image = image.into_rgb8();
+        (image.width(), image.height(), 3, image.into_raw())
+    };
+    let dimension = |value: u32| i32::try_from(value).context("image is too large");
+    let value = Value::from((
+        dimension(width)?,
+        dimension(height)?,
+        dimension(width.saturating_mul(channels))?,
+        has_alpha,
+        8_i32,
+        channels as i32,
+        data,
+    ));
+    OwnedValue::try_from(value).context("failed to convert image data")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn svg(width: u32, height: u32) -> Vec<u8> {
+        format!(r#"<svg xmlns="http://www.w3.org/2000/svg" width="{width}" height="{height}"><rect width="{width}" height="{height}" fill="red"/></svg>"#)
+            .into_bytes()
+    }
+
+    fn rendered(width: u32, height: u32, max_size: Option<u32>) -> (u32, u32) {
+        let image = render_svg(&svg(width, height), max_size).unwrap();
+        (image.width(), image.height())
+    }
+
+    #[test]
+    fn svgs_are_only_scaled_down() {
+        assert_eq!(rendered(16, 8, None), (16, 8));
+        assert_eq!(rendered(16, 8, Some(256)), (16, 8));
+        assert_eq!(rendered(200, 100, Some(50)), (50, 25));
+    }
+
+    #[test]
+    fn svg_size_is_bounded() {
+        assert_eq!(rendered(1_000_000, 500_000, None), (MAX_SVG_SIZE, MAX_SVG_SIZE / 2));
+        assert_eq!(rendered(4000, 4000, Some(5000)), (MAX_SVG_SIZE, MAX_SVG_SIZE));
+    }
+
+    #[test]
+    fn svg_pixels_are_straight_rgba() {
+        let image = render_svg(&svg(2, 2), None).unwrap().into_rgba8();
+        assert_eq!(image.get_pixel(0, 0).0, [255, 0, 0, 255]);
+    }
+}
@@ -1,22 +1,59 @@
 use std::collections::HashMap;
 use std::fmt;
 use std::io::{self, IsTerminal, Read};
-use std::path::PathBuf;
-use std::time::Duration;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{Context, Result, anyhow, bail};
-use clap::{CommandFactory, Parser, ValueEnum};
-use futures_util::StreamExt;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use serde_json::json;
-use zbus::Proxy;
 use zvariant::{OwnedValue, Str};
 
+use crate::backend::{Backend, YamlBackends};
+use crate::body_source::{CommandArg, Selection};
+use crate::config::Config;
+use crate::dbus::ServerLostPolicy;
+use crate::duration::Timeout;
+use crate::format::PayloadFormat;
+use crate::hints::{UrgencySounds, YamlHint};
+use crate::markup::BodyFormat;
+use crate::osc::OscSequence;
+use crate::redact::{RedactConfig, Redactor};
+use crate::sanitize::{Invisible, SanitizePolicy, Sanitizer};
+use crate::schema::SchemaKind;
+use crate::signing::{CardSignature, SigningKey};
+
+mod backend;
+mod body_source;
+mod config;
+mod dbus;
+mod duration;
+mod format;
+mod hex;
+mod hints;
+mod image_data;
+mod markup;
+mod osc;
+mod portal;
+mod redact;
+mod sanitize;
+mod schedule;
+mod schema;
+mod signing;
+mod sink;
+mod spool;
+mod template;
+mod tty;
+mod validate;
+mod yaml_tree;
+
 const NOTIFY_DEST: &str = "org.freedesktop.Notifications";
 const NOTIFY_PATH: &str = "/org/freedesktop/Notifications";
 const NOTIFY_IFACE: &str = "org.freedesktop.Notifications";
+const SIGNATURE_TTL_SECS: u64 = 3600;
 
-#[derive(Debug, Clone, Copy, Deserialize, ValueEnum)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ValueEnum, JsonSchema)]
 #[serde(rename_all = "lowercase")]
 enum Urgency {
     Low,
@@ -34,13 +71,15 @@ impl Urgency {
     }
 }
 
-#[derive(Debug, Parser)]
+#[derive(Debug, Clone, Parser)]
 #[command(
     name = "notify",
     about = "dispatch dbus notifications",
     long_about = None,
     color = clap::ColorChoice::Never,
-    help_template = "{name} - {about}\n\nUsage:\n  notify [options] [summary] [body...]\n\nArguments:\n\n  summary   notification title (overrides YAML summary)\n  body...   notification body text; use '-' to read body text from stdin\n\nOptions:\n{options}"
+    disable_help_subcommand = true,
+    args_conflicts_with_subcommands = true,
+    help_template = "{name} - {about}\n\nUsage:\n  notify [options] [summary] [body...]\n  notify <command> [options]\n\nCommands:\n{subcommands}\n\nArguments:\n\n  summary   notification title (overrides YAML summary)\n  body...   notification body text; use '-' to read body text from stdin\n\nOptions:\n{options}"
 )]
 struct Cli {
     #[arg(value_name = "summary", help = "notification title (overrides YAML summary)")]
@@ -49,22 +88,49 @@ struct Cli {
     #[arg(value_name = "body", trailing_var_arg = true, allow_hyphen_values = true, help = "notification body text; use '-' to read body text from stdin")]
     body: Vec<String>,
 
-    #[arg(long = "file", value_name = "path", help = "read YAML payload from file path, or '-' for stdin")]
+    #[arg(long = "file", value_name = "path", help = "read YAML, JSON or TOML payload from file path, or '-' for stdin")]
     file: Option<PathBuf>,
 
+    #[arg(long = "format", value_enum, value_name = "FORMAT", help = "payload format (default: from the --file extension, else detected from content)")]
+    format: Option<PayloadFormat>,
+
+    #[arg(long = "strict-yaml", help = "reject unquoted YAML scalars that would not be read as text (e.g. 'summary: no') or differ between YAML 1.1 and 1.2")]
+    strict_yaml: bool,
+
+    #[arg(long = "allow-body-command", help = "honour body_file and body_command in stdin and --file payloads, which are otherwise only read from the config and templates")]
+    allow_body_command: bool,
+
+    #[arg(long = "body-format", value_enum, value_name = "FORMAT", help = "how the body is sent to servers that parse markup (default: markup for body arguments, plain for stdin and payload bodies)")]
+    body_format: Option<BodyFormat>,
+
     #[arg(short = 'u', long = "urgency", value_enum, value_name = "URGENCY", help = "urgency level")]
     urgency: Option<Urgency>,
 
     #[arg(short = 'i', long = "icon", value_name = "ICON", help = "icon name or icon file path")]
     icon: Option<String>,
 
+    #[arg(long = "image", value_name = "path", help = "send a PNG, JPEG or SVG file (or '-' for stdin) as image-data hint")]
+    image: Option<PathBuf>,
+
+    #[arg(long = "image-size", value_name = "px", help = "scale --image down to fit this many pixels on its longer side")]
+    image_size: Option<u32>,
+
     #[arg(short = 'a', long = "app-name", value_name = "APP_NAME", help = "application name shown by notification daemon")]
     app_name: Option<String>,
 
     #[arg(short = 'c', long = "category", value_name = "CATEGORY", help = "notification category hint")]
     category: Option<String>,
 
-    #[arg(long = "hint", value_name = "key:value", help = "custom hint (repeatable)")]
+    #[arg(long = "sound", value_name = "name|path", conflicts_with = "silent", help = "sound to play: a themed sound name (sound-name hint) or a sound file path (sound-file hint)")]
+    sound: Option<String>,
+
+    #[arg(long = "silent", help = "ask the server not to play any sound (suppress-sound hint)")]
+    silent: bool,
+
+    #[arg(long = "redact", value_name = "regex", help = "also replace matches of this regular expression with [REDACTED] (repeatable)")]
+    redact: Vec<String>,
+
+    #[arg(long = "hint", value_name = "[type:]key:value", help = "custom hint (repeatable); type is boolean, byte, int, double, string, int16, uint16, uint32, int64, uint64, or array:TYPE / dict:TYPE with comma-separated values")]
     hints: Vec<String>,
 
     #[arg(long = "action", value_name = "id:label", help = "add action button (repeatable)")]
@@ -73,8 +139,8 @@ struct Cli {
     #[arg(long = "progress", value_name = "0-100", help = "progress value hint")]
     progress: Option<u8>,
 
-    #[arg(short = 't', long = "timeout", value_name = "ms", help = "auto-close timeout in milliseconds; with --await also sets await cap to ms+1000")]
-    expire_time: Option<i32>,
+    #[arg(short = 't', long = "timeout", value_name = "duration", allow_negative_numbers = true, help = "auto-close timeout: 30s, 5m, 1h30m, never, default or milliseconds; with --await and no --await-timeout also caps the wait at timeout+1s")]
+    expire_time: Option<Timeout>,
 
     #[arg(long = "id", aliases = ["replace"], value_name = "id", help = "replace existing notification id")]
     replace_id: Option<u32>,
@@ -84,17 +150,159 @@ struct Cli {
 
     #[arg(long = "await", help = "wait until notification closes or an action is selected")]
     await_result: bool,
+
+    #[arg(long = "await-timeout", value_name = "duration", help = "give up --await after this long, independently of --timeout ('never': no cap)")]
+    await_timeout: Option<Timeout>,
+
+    #[arg(long = "await-all", help = "with several payloads, send them all first, then wait for every awaited notification at once")]
+    await_all: bool,
+
+    #[arg(long = "dry-run", help = "print the Notify call (arguments and typed hints) as JSON instead of sending it")]
+    dry_run: bool,
+
+    #[arg(long = "bus-address", value_name = "address", conflicts_with = "system", help = "connect to the D-Bus at address instead of the session bus")]
+    bus_address: Option<String>,
+
+    #[arg(long = "system", help = "connect to the system bus instead of the session bus")]
+    system: bool,
+
+    #[arg(long = "dest", value_name = "name", help = "notification service bus name (default: org.freedesktop.Notifications)")]
+    dest: Option<String>,
+
+    #[arg(long = "backend", value_enum, value_name = "BACKEND", value_delimiter = ',', help = "delivery backend, or comma-separated fallback chain tried in order (default: dbus,osc)")]
+    backends: Vec<Backend>,
+
+    #[arg(long = "osc", value_enum, value_name = "SEQUENCE", help = "escape sequence for the osc backend (default: detected from the terminal)")]
+    osc_sequence: Option<OscSequence>,
+
+    #[arg(long = "sink-file", value_name = "path", help = "output file for the file backend (default: $XDG_STATE_HOME/notify/notifications.jsonl)")]
+    sink_file: Option<PathBuf>,
+
+    #[arg(long = "spool", help = "if no backend can deliver, spool the notification for 'notify flush'")]
+    spool: bool,
+
+    #[arg(long = "spool-ttl", value_name = "seconds", requires = "spool", help = "discard the spooled notification if not redelivered within this many seconds")]
+    spool_ttl: Option<u64>,
+
+    #[arg(long = "at", value_name = "time", help = "deliver later: 14:30 (next occurrence), '2026-03-01 09:00' (local), RFC 3339 or @unix-seconds")]
+    deliver_at: Option<String>,
+
+    #[arg(long = "in", value_name = "duration", conflicts_with = "deliver_at", value_parser = duration::parse_duration, help = "deliver after this long, e.g. 30s, 5m or 1h30m")]
+    deliver_in: Option<u64>,
+
+    #[arg(long = "sign", help = "sign card bodies with the local signing key so the daemon can show them as verified")]
+    sign: bool,
+
+    #[arg(long = "signing-key", value_name = "path", help = "card signing keyfile (default: $XDG_CONFIG_HOME/notify/card.key)")]
+    signing_key: Option<PathBuf>,
+
+    #[arg(long = "on-server-lost", value_enum, value_name = "POLICY", help = "what --await does if the notification server goes away (default: fail)")]
+    on_server_lost: Option<ServerLostPolicy>,
+
+    #[arg(long = "profile", value_name = "name", help = "apply a named profile from the config file")]
+    profile: Option<String>,
+
+    #[arg(long = "template", value_name = "name", help = "payload template name (from $XDG_CONFIG_HOME/notify/templates) or file path")]
+    template: Option<String>,
+
+    #[arg(long = "var", value_name = "key=value", requires = "template", help = "template placeholder value (repeatable)")]
+    vars: Vec<String>,
+
+    #[arg(long = "vars-file", value_name = "path", requires = "template", help = "JSON or YAML file with template placeholder values")]
+    vars_file: Option<PathBuf>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Debug, Clone, Subcommand)]
+enum Command {
+    /// redeliver spooled notifications in order
+    Flush {
+        #[arg(long = "watch", help = "wait for the notification service to appear and keep flushing until the spool is empty")]
+        watch: bool,
+    },
+    /// check payload files for unknown or conflicting fields without sending them
+    Validate {
+        #[arg(value_name = "file", required = true, help = "payload file to check, or '-' for stdin")]
+        files: Vec<PathBuf>,
+
+        #[arg(long = "format", value_enum, value_name = "FORMAT", help = "payload format (default: from the file extension, else detected from content)")]
+        format: Option<PayloadFormat>,
+
+        #[arg(long = "strict-yaml", help = "also report unquoted YAML scalars that are not read as text or differ between YAML 1.1 and 1.2")]
+        strict_yaml: bool,
+    },
+    /// check the signature of a signed card body
+    Verify {
+        #[arg(value_name = "path", help = "card JSON file, or '-' for stdin (default)")]
+        file: Option<PathBuf>,
+        #[arg(long = "signing-key", value_name = "path", help = "card signing keyfile (default: $XDG_CONFIG_HOME/notify/card.key)")]
+        signing_key: Option<PathBuf>,
+    },
+    /// print the JSON Schema of payloads, card bodies or event output
+    Schema {
+        #[arg(value_enum, value_name = "KIND", default_value = "payload", help = "which schema to print")]
+        kind: SchemaKind,
+    },
+    /// inspect the configuration file
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommand,
+    },
+    /// list or cancel notifications scheduled with --at or --in
+    Scheduled {
+        #[command(subcommand)]
+        command: ScheduledCommand,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand)]
+enum ConfigCommand {
+    /// print the effective default settings and where each came from
+    Show {
+        #[arg(long = "profile", value_name = "name", help = "layer this profile over the defaults")]
+        profile: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, Subcommand)]
+enum ScheduledCommand {
+    /// print pending notifications, next due first
+    List,
+    /// remove pending notifications
+    Cancel {
+        #[arg(value_name = "id", help = "id from 'notify scheduled list'")]
+        ids: Vec<String>,
+        #[arg(long = "all", conflicts_with = "ids", help = "cancel every pending notification")]
+        all: bool,
+    },
+    /// deliver pending notifications when due; exits once none are left
+    Run,
 }
 
 impl Cli {
+    fn image_from_stdin(&self) -> bool {
+        self.image.as_ref().is_some_and(|image| image.as_os_str() == "-")
+    }
+
     fn is_empty_invocation(&self) -> bool {
         self.summary.is_none()
             && self.body.is_empty()
             && self.file.is_none()
+            && self.format.is_none()
+            && !self.strict_yaml
+            && !self.allow_body_command
+            && self.body_format.is_none()
             && self.urgency.is_none()
             && self.icon.is_none()
+            && self.image.is_none()
+            && self.image_size.is_none()
             && self.app_name.is_none()
             && self.category.is_none()
+            && self.sound.is_none()
+            && !self.silent
+            && self.redact.is_empty()
             && self.hints.is_empty()
             && self.actions.is_empty()
             && self.progress.is_none()
@@ -102,34 +310,141 @@ impl Cli {
             && self.replace_id.is_none()
             && !self.print_id
             && !self.await_result
+            && self.await_timeout.is_none()
+            && !self.await_all
+            && !self.dry_run
+            && self.bus_address.is_none()
+            && !self.system
+            && self.dest.is_none()
+            && self.backends.is_empty()
+            && self.osc_sequence.is_none()
+            && self.sink_file.is_none()
+            && !self.spool
+            && self.spool_ttl.is_none()
+            && self.deliver_at.is_none()
+            && self.deliver_in.is_none()
+            && !self.sign
+            && self.signing_key.is_none()
+            && self.on_server_lost.is_none()
+            && self.profile.is_none()
+            && self.template.is_none()
+            && self.vars.is_empty()
+            && self.vars_file.is_none()
     }
 }
 
-#[derive(Debug, Default, Deserialize)]
+/// A notification payload, as YAML, JSON or TOML.
+#[derive(Debug, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+#[schemars(title = "notify payload", transform = add_profile_key)]
 struct YamlPayload {
+    /// notification title
     summary: Option<String>,
+    /// notification body text
     body: Option<String>,
+    /// read the body from this file
+    body_file: Option<PathBuf>,
+    /// run this command (argv list, no shell) and use its output as the body
+    body_command: Option<Vec<CommandArg>>,
+    /// keep only the first this many lines of body_file or body_command
+    body_head: Option<usize>,
+    /// keep only the last this many lines of body_file or body_command
+    body_tail: Option<usize>,
+    /// read at most this many bytes of body_file or body_command (default: 65536); the last ones with body_tail
+    body_max_bytes: Option<u64>,
+    /// stop body_command after this long, e.g. "30s", or "never" (default: 10s)
+    body_timeout: Option<Timeout>,
+    /// plain (escaped for servers that parse markup, the default), markup (the spec's b/i/u/a/img subset) or markdown
+    body_format: Option<BodyFormat>,
     urgency: Option<Urgency>,
+    /// icon name or file path
     icon: Option<String>,
+    /// PNG, JPEG or SVG file sent as image-data hint
+    image: Option<PathBuf>,
+    /// scale image down to fit this many pixels on its longer side
+    image_size: Option<u32>,
+    /// application name (default: notify)
     app_name: Option<String>,
+    /// notification category hint, e.g. "im.received"
     category: Option<String>,
+    /// themed sound name, or a sound file path (containing '/' or with a file extension)
+    sound: Option<String>,
+    /// ask the server not to play any sound
+    silent: Option<bool>,
+    /// default sound per urgency when no sound is set, e.g. in the config defaults
+    sounds: Option<UrgencySounds>,
+    /// cleaning and length limits for summary, body, action labels and card text
+    sanitize: Option<SanitizePolicy>,
+    /// secret redaction in summary, body, action labels and all card text
+    redact: Option<RedactConfig>,
+    /// extra notification hints: plain values (integers are sent as int64) or {type, value}
     #[serde(default)]
-    hints: HashMap<String, serde_yaml::Value>,
+    hints: HashMap<String, YamlHint>,
+    /// action buttons as "id:label" strings or {id, label} mappings
     #[serde(default)]
     actions: Vec<YamlAction>,
+    /// progress percentage (0-100)
     progress: Option<u8>,
-    timeout: Option<i32>,
-    expire_time: Option<i32>,
+    /// auto-close timeout: a duration such as "30s" or "1h30m", "never", "default", or milliseconds
+    timeout: Option<Timeout>,
+    /// alias of timeout
+    expire_time: Option<Timeout>,
+    /// id of the notification to replace
     id: Option<u32>,
+    /// alias of id
     replace: Option<u32>,
+    /// print the notification id
     print_id: Option<bool>,
+    /// wait until the notification closes or an action is selected
     #[serde(rename = "await")]
     await_result: Option<bool>,
+    /// give up waiting after this duration (default: timeout + 1s if timeout is set, "never": no cap)
+    await_timeout: Option<Timeout>,
     card: Option<YamlCard>,
+    /// D-Bus address to connect to instead of the session bus
+    bus_address: Option<String>,
+    /// connect to the system bus
+    system: Option<bool>,
+    /// notification service bus name (default: org.freedesktop.Notifications)
+    dest: Option<String>,
+    /// delivery backend, or a fallback chain tried in order
+    backend: Option<YamlBackends>,
+    /// terminal escape sequence for the osc backend (default: detected)
+    osc: Option<OscSequence>,
+    /// file the file backend appends to
+    sink_file: Option<PathBuf>,
+    /// spool the notification if no backend can deliver it
+    spool: Option<bool>,
+    /// seconds before a spooled notification is discarded
+    spool_ttl: Option<u64>,
+    /// deliver later: "14:30" (next occurrence), "2026-03-01 09:00" (local time), RFC 3339 or "@UNIX-SECONDS"
+    deliver_at: Option<String>,
+    /// sign card bodies with the local signing key (ignored without a card)
+    sign: Option<bool>,
+    /// card signing keyfile (default: $XDG_CONFIG_HOME/notify/card.key)
+    signing_key: Option<PathBuf>,
+    /// seconds a card signature stays valid (default: 3600)
+    signature_ttl: Option<u64>,
+    on_server_lost: Option<ServerLostPolicy>,
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(tag = "type", rename_all = "kebab-case")]
+/// `profile` is consumed while layering the config, before the payload is
+/// parsed, so it is only part of the schema.
+fn add_profile_key(schema: &mut schemars::Schema) {
+    if let Some(properties) = schema.get_mut("properties").and_then(|properties| properties.as_object_mut()) {
+        properties.insert(
+            "profile".to_string(),
+            serde_json::json!({
+                "description": "named profile from the config file, resolved before the payload is parsed",
+                "type": ["string", "null"],
+            }),
+        );
+    }
+}
+
+/// An interactive card rendered into the body for xnotid.
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(tag = "type", rename_all = "kebab-case", deny_unknown_fields)]
 enum YamlCard {
     MultipleChoice {
         question: String,
@@ -143,20 +458,20 @@ enum YamlCard {
     },
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(untagged)]
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(untagged, deny_unknown_fields)]
 enum YamlCardChoice {
     Label(String),
     Object { id: String, label: String },
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 struct CardChoice {
     id: String,
     label: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, JsonSchema)]
 #[serde(tag = "type", rename_all = "kebab-case")]
 enum CardPayload {
     MultipleChoice {
@@ -170,11 +485,16 @@ enum CardPayload {
     },
 }
 
-#[derive(Debug, Serialize)]
+/// The card JSON sent as the notification body (with the x-card hint).
+#[derive(Debug, Serialize, JsonSchema)]
+#[schemars(title = "notify card body")]
 struct CardEnvelope {
     xnotid_card: String,
     #[serde(flatten)]
     payload: CardPayload,
+    /// present when the card was sent with --sign
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signature: Option<CardSignature>,
 }
 
 struct CardRender {
@@ -183,26 +503,95 @@ struct CardRender {
     default_summary: String,
 }
 
-#[derive(Debug, Deserialize)]
-#[serde(untagged)]
+#[derive(Debug, Deserialize, JsonSchema)]
+#[serde(untagged, deny_unknown_fields)]
 enum YamlAction {
     Pair(String),
     Object { id: String, label: String },
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Bus {
+    Session,
+    System,
+    Address(String),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
 struct Request {
     app_name: String,
     replaces_id: u32,
     icon: String,
     summary: String,
     body: String,
+    /// `None` sends the body verbatim (card JSON, notifications spooled before
+    /// body formats existed).
+    #[serde(default)]
+    body_format: Option<BodyFormat>,
+    /// Keep `img` tags in a markup body (`sanitize.images`).
+    #[serde(default)]
+    markup_images: bool,
+    /// Untruncated body, saved and named in the body when delivered.
+    #[serde(default)]
+    full_text: Option<String>,
+    /// The saved full text, noted after the rendered body.
+    #[serde(default)]
+    full_text_path: Option<PathBuf>,
     actions: Vec<String>,
+    #[serde(with = "spool::hints_codec")]
     hints: HashMap<String, OwnedValue>,
+    /// `image-path` fallback for servers older than spec 1.2 (no `image-data`).
+    #[serde(default)]
+    image_path: Option<String>,
+    /// Nonce of a signed card; D-Bus action ids carry it and `--await` checks it.
+    #[serde(default)]
+    card_nonce: Option<String>,
     expire_timeout: i32,
     print_id: bool,
     await_result: bool,
     await_timeout_ms: Option<u64>,
+    bus: Bus,
+    dest: String,
+    backends: Vec<Backend>,
+    report_backend: bool,
+    urgency: Urgency,
+    osc_sequence: Option<OscSequence>,
+    sink_file: Option<PathBuf>,
+    spool: bool,
+    spool_ttl: Option<u64>,
+    on_server_lost: ServerLostPolicy,
+    /// Unix time in milliseconds to deliver at (`--at`, `--in`, `deliver_at`).
+    #[serde(default)]
+    deliver_at: Option<u64>,
+    /// Number of secrets redacted, reported on stderr.
+    #[serde(skip)]
+    redacted: usize,
+}
+
+impl Request {
+    /// Saves the untruncated body (`sanitize.full_text`) so the body can point
+    /// to it. Done at delivery so `--dry-run` and `validate` write nothing.
+    fn attach_full_text(&mut self) -> Result<()> {
+        if let Some(full_text) = self.full_text.take() {
+            self.full_text_path = Some(sanitize::save_full_text(&full_text)?);
+        }
+        Ok(())
+    }
+
+    /// The body for a receiver that parses markup (`markup`) or shows plain
+    /// text. The full text note is plain text, whatever the body format.
+    fn render_body(&self, markup: bool) -> String {
+        let mut body = match self.body_format {
+            Some(format) => format.render(&self.body, markup, self.markup_images),
+            None => self.body.clone(),
+        };
+        if let Some(path) = &self.full_text_path {
+            let note = sanitize::full_text_note(path);
+            body.push_str(&if markup { markup::escape(&note) } else { note });
+        }
+        body
+    }
 }
 
 #[derive(Debug)]
@@ -218,6 +607,19 @@ impl fmt::Display for AwaitTimeoutError {
 
 impl std::error::Error for AwaitTimeoutError {}
 
+#[derive(Debug)]
+struct ServerLostError {
+    dest: String,
+}
+
+impl fmt::Display for ServerLostError {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "notification server '{}' went away during --await", self.dest)
+    }
+}
+
+impl std::error::Error for ServerLostError {}
+
 #[tokio::main]
 async fn main() {
     if let Err(error) = run().await {
@@ -225,17 +627,53 @@ async fn main() {
             eprintln!("error: {error:#}");
             std::process::exit(124);
         }
+        if error.downcast_ref::<ServerLostError>().is_some() {
+            eprintln!("error: {error:#}");
+            std::process::exit(69);
+        }
         eprintln!("error: {error:#}");
         std::process::exit(1);
     }
 }
 
 async fn run() -> Result<()> {
-    let cli = Cli::parse();
+    let mut cli = Cli::parse();
+
+    if let Some(command) = cli.command.take() {
+        return match command {
+            Command::Flush { watch } => {
+                schedule::resume();
+                spool::flush(watch).await
+            }
+            Command::Validate {
+                files,
+                format,
+                strict_yaml,
+            } => validate::run(&files, format, strict_yaml),
+            Command::Verify { file, signing_key } => {
+                signing::run_verify(file.as_deref(), signing_key.as_deref())
+            }
+            Command::Schema { kind } => schema::print(kind),
+            Command::Config {
+                command: ConfigCommand::Show { profile },
+            } => config::show(profile.as_deref()),
+            Command::Scheduled { command } => match command {
+                ScheduledCommand::List => schedule::list(),
+                ScheduledCommand::Cancel { ids, all } => schedule::cancel(&ids, all),
+                ScheduledCommand::Run => schedule::run().await,
+            },
+        };
+    }
 
     if cli.file.is_some() && cli.body.len() == 1 && cli.body[0] == "-" {
         bail!("cannot use BODY='-' together with --file");
     }
+    if cli.image_from_stdin()
+        && (cli.file.as_ref().is_some_and(|file| file.as_os_str() == "-")
+            || (cli.body.len() == 1 && cli.body[0] == "-"))
+    {
+        bail!("cannot read --image from stdin together with another stdin input");
+    }
 
     if cli.is_empty_invocation() && io::stdin().is_terminal() {
         let mut command = Cli::command();
@@ -250,77 +688,200 @@ async fn run() -> Result<()> {
         bail!("--progress must be between 0 and 100");
     }
 
-    let stdin_body = load_stdin_body_if_requested(&cli)?;
-    let payload = load_yaml_payload(&cli)?;
-    let request = merge_request(cli, payload, stdin_body)?;
-
-    let connection = zbus::Connection::session()
-        .await
-        .context("failed to connect to session D-Bus")?;
-
-    let proxy = Proxy::new(&connection, NOTIFY_DEST, NOTIFY_PATH, NOTIFY_IFACE)
-        .await
-        .context("failed to create notifications proxy")?;
-
-    let notification_id: u32 = proxy
-        .call(
-            "Notify",
-            &(
-                request.app_name,
-                request.replaces_id,
-                request.icon,
-                request.summary,
-                request.body,
-                request.actions,
-                request.hints,
-                request.expire_timeout,
-            ),
-        )
-        .await
-        .context("failed to send desktop notification")?;
-
-    if request.print_id {
-        println!("{notification_id}");
-    }
-
-    if request.await_result {
-        await_notification_result(
-            &proxy,
-            notification_id,
-            request.print_id,
-            request.await_timeout_ms,
-        )
-        .await?;
+    let stdin = StdinInput {
+        body: load_stdin_body_if_requested(&cli)?,
+        image: load_stdin_image_if_requested(&cli)?,
+    };
+    let mut payloads = load_payloads(&cli)?;
+    let template = match &cli.template {
+        Some(name) => {
+            let vars = template::Vars::collect(&cli.vars, cli.vars_file.as_deref())?;
+            let rendered = template::render(template::load(name)?, &vars)
+                .with_context(|| format!("failed to render template '{name}'"))?;
+            Some((name.as_str(), rendered))
+        }
+        None => None,
+    };
+    let config = Config::load()?;
+
+    if payloads.len() > 1 || cli.await_all {
+        let requests = payloads
+            .into_iter()
+            .map(|payload| {
+                let layered = config.layer(cli.profile.as_deref(), template.clone(), Some(payload?))?;
+                check_payload_trust(&layered, cli.allow_body_command)?;
+                merge_request(cli.clone(), Some(layered.into_payload()?), &stdin)
+            })
+            .enumerate()
+            .map(|(index, request)| {
+                if let Ok(request) = &request
+                    && request.redacted > 0
+                {
+                    eprintln!("warning: document {}: {}", index + 1, redaction_note(request.redacted));
+                }
+                request
+            })
+            .collect();
+        if cli.dry_run {
+            return dbus::dry_run(requests);
+        }
+        schedule::resume();
+        return backend::deliver_batch(requests, cli.await_all).await;
+    }
+
+    let layered = config.layer(cli.profile.as_deref(), template, payloads.pop().transpose()?)?;
+    check_payload_trust(&layered, cli.allow_body_command)?;
+    let dry_run = cli.dry_run;
+    let request = merge_request(cli, Some(layered.into_payload()?), &stdin)?;
+    if request.redacted > 0 {
+        eprintln!("warning: {}", redaction_note(request.redacted));
+    }
+    if dry_run {
+        return dbus::dry_run(vec![Ok(request)]);
     }
 
+    schedule::resume();
+    backend::deliver(request).await
+}
+
+/// Payload keys that read files or run programs.
+const TRUSTED_KEYS: [&str; 2] = ["body_file", "body_command"];
+
+/// Stdin and `--file` payloads are untrusted data, so they may only read
+/// files or run programs with `--allow-body-command`. The config file,
+/// profiles and templates are the user's own.
+fn check_payload_trust(layered: &config::Layered, allowed: bool) -> Result<()> {
+    if allowed {
+        return Ok(());
+    }
+    for key in TRUSTED_KEYS {
+        if layered.source(key) == Some(&config::Source::Payload) {
+            bail!(
+                "'{key}' is not allowed in stdin or --file payloads; set it in the config, a profile or a template, or pass --allow-body-command"
+            );
+        }
+    }
     Ok(())
 }
 
-fn load_yaml_payload(cli: &Cli) -> Result<Option<YamlPayload>> {
+fn redaction_note(count: usize) -> String {
+    format!("redacted {count} secret{}", if count == 1 { "" } else { "s" })
+}
+
+fn generate_notification_id() -> u32 {
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.subsec_nanos())
+        .unwrap_or_default();
+    (std::process::id().rotate_left(16) ^ nanos).max(1)
+}
+
+fn state_dir() -> Result<PathBuf> {
+    std::env::var_os("XDG_STATE_HOME")
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .ok_or_else(|| anyhow!("cannot determine state directory; set XDG_STATE_HOME or HOME"))
+}
+
+fn config_dir() -> Result<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .ok_or_else(|| anyhow!("cannot determine config directory; set XDG_CONFIG_HOME or HOME"))
+}
+
+/// The payload documents, each checked on its own so one invalid document of a
+/// batch does not keep the others from being sent.
+fn load_payloads(cli: &Cli) -> Result<Vec<Result<serde_yaml::Value>>> {
     let mut input = String::new();
 
     if let Some(path) = &cli.file {
         if path.as_os_str() == "-" {
             io::stdin()
                 .read_to_string(&mut input)
-                .context("failed to read YAML from stdin")?;
+                .context("failed to read payload from stdin")?;
         } else {
             input = std::fs::read_to_string(path)
-                .with_context(|| format!("failed to read YAML file: {}", path.display()))?;
+                .with_context(|| format!("failed to read payload file: {}", path.display()))?;
         }
-    } else if !io::stdin().is_terminal() && cli.body.is_empty() {
+    } else if !io::stdin().is_terminal()
+        && cli.body.is_empty()
+        && !cli.image_from_stdin()
+    {
         io::stdin()
             .read_to_string(&mut input)
-            .context("failed to read YAML from stdin")?;
+            .context("failed to read payload from stdin")?;
     }
 
     if input.trim().is_empty() {
-        return Ok(None);
+        return Ok(Vec::new());
     }
 
-    let payload: YamlPayload =
-        serde_yaml::from_str(&input).context("failed to parse YAML payload")?;
-    Ok(Some(payload))
+    let format = PayloadFormat::resolve(cli.format, cli.file.as_deref(), &input);
+    let mut payloads = format::parse_payloads(&input, format, cli.strict_yaml)?;
+    let documents = validate::located_documents(&input, format, &payloads);
+    if let Some(file) = &cli.file {
+        resolve_relative_paths(&mut payloads, file);
+    }
+    Ok(payloads
+        .into_iter()
+        .zip(&documents)
+        .map(|(payload, document)| {
+            let problems = validate::check_documents(std::slice::from_ref(document));
+            validate::ensure_valid(&problems, "payload").map(|()| payload)
+        })
+        .collect())
+}
+
+/// Payload fields naming files, resolved against the payload file's directory.
+const PATH_FIELDS: [&str; 2] = ["image", "body_file"];
+
+/// Makes relative paths in the payloads of `file` independent of the current
+/// directory. Payloads from stdin keep theirs relative to the current directory.
+fn resolve_relative_paths(payloads: &mut [serde_yaml::Value], file: &Path) {
+    let Some(dir) = file.parent().filter(|_| file.as_os_str() != "-") else {
+        return;
+    };
+    for payload in payloads {
+        let serde_yaml::Value::Mapping(mapping) = payload else {
+            continue;
+        };
+        for field in PATH_FIELDS {
+            if let Some(serde_yaml::Value::String(path)) = mapping.get_mut(field)
+                && path != "-"
+                && Path::new(path.as_str()).is_relative()
+            {
+                *path = dir.join(&*path).to_string_lossy().into_owned();
+            }
+        }
+
+        // Themed sound names are not paths.
+        let mut sounds = Vec::new();
+        for (key, value) in mapping.iter_mut() {
+            match (key.as_str(), value) {
+                (Some("sound"), value) => sounds.push(value),
+                (Some("sounds"), serde_yaml::Value::Mapping(defaults)) => sounds.extend(defaults.values_mut()),
+                _ => {}
+            }
+        }
+        for sound in sounds {
+            if let serde_yaml::Value::String(sound) = sound
+                && let Some(path) = hints::sound_path(sound)
+                && Path::new(path).is_relative()
+            {
+                *sound = dir.join(path).to_string_lossy().into_owned();
+            }
+        }
+    }
+}
+
+/// Stdin read once up front, so every document of a batch shares it.
+#[derive(Default)]
+struct StdinInput {
+    body: Option<String>,
+    image: Option<Vec<u8>>,
 }
 
 fn load_stdin_body_if_requested(cli: &Cli) -> Result<Option<String>> {
@@ -335,24 +896,40 @@ fn load_stdin_body_if_requested(cli: &Cli) -> Result<Option<String>> {
     Ok(Some(body))
 }
 
-fn merge_request(cli: Cli, payload: Option<YamlPayload>, stdin_body: Option<String>) -> Result<Request> {
+fn load_stdin_image_if_requested(cli: &Cli) -> Result<Option<Vec<u8>>> {
+    if !cli.image_from_stdin() {
+        return Ok(None);
+    }
+
+    let mut image = Vec::new();
+    io::stdin()
+        .read_to_end(&mut image)
+        .context("failed to read image from stdin")?;
+    Ok(Some(image))
+}
+
+fn merge_request(cli: Cli, payload: Option<YamlPayload>, stdin: &StdinInput) -> Result<Request> {
     let payload = payload.unwrap_or_default();
+    let policy = payload.sanitize.clone().unwrap_or_default();
+    let redactor = Redactor::new(&payload.redact.clone().unwrap_or_default(), &cli.redact)?;
+    let sanitizer = Sanitizer::new(&policy, &redactor);
 
     let mut hints = HashMap::<String, OwnedValue>::new();
-    for (key, value) in payload.hints {
-        hints.insert(key, yaml_value_to_owned_value(value)?);
+    for (key, hint) in payload.hints {
+        let value = hints::from_yaml(&key, &hint)?;
+        hints.insert(key, value);
     }
 
     let mut actions = Vec::<String>::new();
     for action in payload.actions {
         let (id, label) = parse_yaml_action(action)?;
         actions.push(id);
-        actions.push(label);
+        actions.push(sanitizer.label(&label));
     }
     for action in cli.actions {
         let (id, label) = parse_cli_action(&action)?;
         actions.push(id);
-        actions.push(label);
+        actions.push(sanitizer.label(&label));
     }
 
     let body_from_cli = if cli.body.is_empty() || (cli.body.len() == 1 && cli.body[0] == "-") {
@@ -360,14 +937,40 @@ fn merge_request(cli: Cli, payload: Option<YamlPayload>, stdin_body: Option<Stri
     } else {
         Some(cli.body.join(" "))
     };
-
-    let mut summary = sanitize_text(cli.summary.or(payload.summary).unwrap_or_default());
-    let mut body = sanitize_text(
-        stdin_body
-            .or(body_from_cli)
-            .or(payload.body)
-            .unwrap_or_default(),
+    // Body arguments are written by the caller, like notify-send's; stdin and
+    // payload bodies may come from anywhere.
+    let default_body_format = if stdin.body.is_none() && body_from_cli.is_some() {
+        BodyFormat::Markup
+    } else {
+        BodyFormat::Plain
+    };
+    let mut body_format = Some(
+        cli.body_format
+            .or(payload.body_format)
+            .unwrap_or(default_body_format),
     );
+
+    let mut summary = sanitizer.summary(&cli.summary.or(payload.summary).unwrap_or_default());
+    let body = match stdin.body.clone().or(body_from_cli).or(payload.body) {
+        Some(body) => body,
+        None => {
+            let selection = Selection {
+                head: payload.body_head,
+                tail: payload.body_tail,
+                max_bytes: payload.body_max_bytes.unwrap_or(body_source::DEFAULT_MAX_BYTES),
+            };
+            if selection.head.is_some() && selection.tail.is_some() {
+                bail!("cannot combine 'body_head' with 'body_tail'");
+            }
+            match (&payload.body_file, &payload.body_command) {
+                (Some(_), Some(_)) => bail!("cannot combine 'body_file' with 'body_command'"),
+                (Some(path), None) => body_source::read_file(path, &selection)?,
+                (None, Some(argv)) => body_source::run_command(argv, &selection, payload.body_timeout)?,
+                (None, None) => String::new(),
+            }
+        }
+    };
+    let (mut body, mut full_text) = sanitizer.body(&body)?;
     let app_name = sanitize_text(
         cli.app_name
             .or(payload.app_name)
@@ -397,26 +1000,71 @@ fn merge_request(cli: Cli, payload: Option<YamlPayload>, stdin_body: Option<Stri
         hints.insert("value".to_string(), OwnedValue::from(i32::from(value)));
     }
 
+    // CLI over payload; within the payload layers, silence wins over a sound.
+    if cli.silent || (cli.sound.is_none() && payload.silent.unwrap_or(false)) {
+        hints.remove("sound-file");
+        hints.remove("sound-name");
+        hints.insert("suppress-sound".to_string(), OwnedValue::from(true));
+    } else {
+        let has_sound_hint = hints.contains_key("sound-file") || hints.contains_key("sound-name");
+        let sound = cli.sound.or(payload.sound).or_else(|| {
+            let sounds = payload.sounds.as_ref().filter(|_| !has_sound_hint)?;
+            sounds.get(urgency).map(str::to_string)
+        });
+        if let Some(sound) = sound {
+            let (key, value) = hints::sound(&sound)?;
+            hints.remove("sound-file");
+            hints.remove("sound-name");
+            hints.insert(key, value);
+        }
+    }
+
     for raw_hint in cli.hints {
-        let (key, value) = parse_cli_hint(&raw_hint)?;
+        let (key, value) = hints::parse_cli(&raw_hint)?;
         hints.insert(key, value);
     }
+    if let Some(path) = hints.get("sound-file") {
+        let path = <&str>::try_from(path).context("hint 'sound-file' must be a string")?;
+        hints::check_sound_file(path)?;
+    }
+
+    let image = cli.image.or(payload.image);
+    let image_size = cli.image_size.or(payload.image_size);
+    let mut image_path = None;
+    let mut card_nonce = None;
+    if let Some(path) = image {
+        hints.insert("image-data".to_string(), image_data::load(&path, stdin.image.as_deref(), image_size)?);
+        if path.as_os_str() != "-" {
+            let path = std::path::absolute(&path)
+                .with_context(|| format!("failed to resolve image path: {}", path.display()))?;
+            image_path = Some(path.to_string_lossy().into_owned());
+        }
+    }
 
     if let Some(card) = payload.card {
         if !body.is_empty() {
             bail!("cannot combine 'card' with explicit body input; use one or the other");
         }
 
-        let card_render = render_card(card)?;
-        body = sanitize_text(card_render.body_json);
+        let card_render = render_card(card, &sanitizer)?;
+        body = card_render.body_json;
+        if cli.sign || payload.sign.unwrap_or(false) {
+            let key = SigningKey::load(cli.signing_key.or(payload.signing_key).as_deref())?;
+            let ttl = payload.signature_ttl.unwrap_or(SIGNATURE_TTL_SECS);
+            let (signed, nonce) = key.sign(&body, ttl)?;
+            body = signed;
+            card_nonce = Some(nonce);
+        }
+        body_format = None;
+        full_text = None;
         if summary.is_empty() {
             summary = card_render.default_summary;
         }
 
         if actions.is_empty() {
             for (id, label) in card_render.actions {
-                actions.push(sanitize_text(id));
-                actions.push(sanitize_text(label));
+                actions.push(id);
+                actions.push(label);
             }
         }
 
@@ -433,13 +1081,64 @@ fn merge_request(cli: Cli, payload: Option<YamlPayload>, stdin_body: Option<Stri
         .expire_time
         .or(payload.expire_time)
         .or(payload.timeout)
-        .unwrap_or(-1);
+        .unwrap_or(Timeout::Default)
+        .expire_timeout()?;
     let print_id = cli.print_id || payload.print_id.unwrap_or(false);
     let await_result = cli.await_result || payload.await_result.unwrap_or(false);
-    let await_timeout_ms = if await_result && expire_timeout >= 0 {
-        Some(expire_timeout as u64 + 1000)
+    let await_timeout_ms = match cli.await_timeout.or(payload.await_timeout) {
+        _ if !await_result => None,
+        Some(Timeout::After(millis)) => Some(millis),
+        Some(Timeout::Never) => None,
+        // Without an explicit cap, a set timeout bounds the wait as well.
+        Some(Timeout::Default) | None if expire_timeout >= 0 => Some(expire_timeout as u64 + 1000),
+        Some(Timeout::Default) | None => None,
+    };
+
+    let bus = if let Some(address) = cli.bus_address {
+        Bus::Address(address)
+    } else if cli.system {
+        Bus::System
     } else {
-        None
+        match (payload.bus_address, payload.system.unwrap_or(false)) {
+            (Some(_), true) => bail!("cannot combine 'bus_address' with 'system: true'"),
+            (Some(address), false) => Bus::Address(address),
+            (None, true) => Bus::System,
+            (None, false) => Bus::Session,
+        }
+    };
+    let dest = cli
+        .dest
+        .or(payload.dest)
+        .unwrap_or_else(|| NOTIFY_DEST.to_string());
+    if dest.trim().is_empty() {
+        bail!("notification service name cannot be empty");
+    }
+    let backends = if cli.backends.is_empty() {
+        payload.backend.map(YamlBackends::into_vec).unwrap_or_default()
+    } else {
+        cli.backends
+    };
+    let report_backend = backends.len() > 1;
+    let backends = if backends.is_empty() {
+        Backend::DEFAULT_CHAIN.to_vec()
+    } else {
+        backends
+    };
+    let osc_sequence = cli.osc_sequence.or(payload.osc);
+    let sink_file = cli.sink_file.or(payload.sink_file);
+    let spool = cli.spool || payload.spool.unwrap_or(false);
+    let spool_ttl = cli.spool_ttl.or(payload.spool_ttl);
+    let on_server_lost = cli
+        .on_server_lost
+        .or(payload.on_server_lost)
+        .unwrap_or_default();
+    if spool && await_result {
+        bail!("cannot combine spooling with --await; spooled notifications cannot be awaited");
+    }
+    let deliver_at = match (cli.deliver_in, cli.deliver_at.or(payload.deliver_at)) {
+        (Some(delay), _) => Some(schedule::now_ms().saturating_add(delay)),
+        (None, Some(time)) => Some(schedule::parse_time(&time)?),
+        (None, None) => None,
     };
 
     Ok(Request {
@@ -448,16 +1147,34 @@ fn merge_request(cli: Cli, payload: Option<YamlPayload>, stdin_body: Option<Stri
         icon,
         summary,
         body,
+        body_format,
+        markup_images: policy.images(),
+        full_text,
+        full_text_path: None,
         actions,
         hints,
+        image_path,
+        card_nonce,
         expire_timeout,
         print_id,
         await_result,
         await_timeout_ms,
+        bus,
+        dest,
+        backends,
+        report_backend,
+        urgency,
+        osc_sequence,
+        sink_file,
+        spool,
+        spool_ttl,
+        on_server_lost,
+        deliver_at,
+        redacted: sanitizer.redacted(),
     })
 }
 
-fn render_card(card: YamlCard) -> Result<CardRender> {
+fn render_card(card: YamlCard, sanitizer: &Sanitizer<'_>) -> Result<CardRender> {
     match card {
         YamlCard::MultipleChoice {
             question,
@@ -472,16 +1189,16 @@ fn render_card(card: YamlCard) -> Result<CardRender> {
             let mut actions = Vec::with_capacity(choices.len());
 
             for (index, choice) in choices.into_iter().enumerate() {
+                // Ids derived from a label use the redacted label.
                 let (id, label) = match choice {
                     YamlCardChoice::Label(label) => {
-                        let id = normalize_choice_id(&label, index + 1);
-                        (id, label)
+                        let label = sanitizer.label(label.trim());
+                        (normalize_choice_id(&label, index + 1), label)
                     }
-                    YamlCardChoice::Object { id, label } => (id, label),
+                    YamlCardChoice::Object { id, label } => (id, sanitizer.label(label.trim())),
                 };
 
                 let id = sanitize_text(id.trim().to_string());
-                let label = sanitize_text(label.trim().to_string());
                 if id.is_empty() || label.is_empty() {
                     bail!("card choices must have non-empty id and label");
                 }
@@ -496,10 +1213,11 @@ fn render_card(card: YamlCard) -> Result<CardRender> {
             let envelope = CardEnvelope {
                 xnotid_card: "v1".to_string(),
                 payload: CardPayload::MultipleChoice {
-                    question: sanitize_text(question),
+                    question: sanitizer.question(&question),
                     choices: normalized_choices,
                     allow_other,
                 },
+                signature: None,
             };
             let body_json = serde_json::to_string(&envelope)
                 .context("failed to serialize multiple-choice card body")?;
@@ -514,13 +1232,14 @@ fn render_card(card: YamlCard) -> Result<CardRender> {
             question,
             allow_label,
         } => {
-            let allow_label = sanitize_text(allow_label.unwrap_or_else(|| "Allow".to_string()));
+            let allow_label = sanitizer.label(allow_label.as_deref().unwrap_or("Allow"));
             let envelope = CardEnvelope {
                 xnotid_card: "v1".to_string(),
                 payload: CardPayload::Permission {
-                    question: sanitize_text(question),
+                    question: sanitizer.question(&question),
                     allow_label: allow_label.clone(),
                 },
+                signature: None,
             };
             let body_json = serde_json::to_string(&envelope)
                 .context("failed to serialize permission card body")?;
@@ -557,7 +1276,7 @@ fn normalize_choice_id(label: &str, fallback_index: usize) -> String {
 fn parse_yaml_action(action: YamlAction) -> Result<(String, String)> {
     match action {
         YamlAction::Pair(value) => parse_cli_action(&value),
-        YamlAction::Object { id, label } => Ok((sanitize_text(id), sanitize_text(label))),
+        YamlAction::Object { id, label } => Ok((sanitize_text(id), label)),
     }
 }
 
@@ -566,140 +1285,43 @@ fn parse_cli_action(input: &str) -> Result<(String, String)> {
         .split_once(':')
         .ok_or_else(|| anyhow!("invalid --action '{input}', expected ID:LABEL"))?;
     let id = sanitize_text(id.trim().to_string());
-    let label = sanitize_text(label.trim().to_string());
-    if id.is_empty() || label.is_empty() {
+    let label = label.trim().to_string();
+    if id.is_empty() || sanitize_text(label.clone()).is_empty() {
         bail!("invalid --action '{input}', ID and LABEL must be non-empty");
     }
     Ok((id, label))
 }
 
-fn parse_cli_hint(input: &str) -> Result<(String, OwnedValue)> {
-    let (key, raw_value) = input
-        .split_once(':')
-        .ok_or_else(|| anyhow!("invalid --hint '{input}', expected KEY:VALUE"))?;
-    let key = key.trim();
-    if key.is_empty() {
-        bail!("invalid --hint '{input}', key cannot be empty");
-    }
-    let value = parse_string_value(raw_value.trim());
-    Ok((key.to_string(), value))
-}
-
-fn parse_string_value(value: &str) -> OwnedValue {
-    if value.eq_ignore_ascii_case("true") {
-        return OwnedValue::from(true);
-    }
-    if value.eq_ignore_ascii_case("false") {
-        return OwnedValue::from(false);
-    }
-    if let Ok(number) = value.parse::<i64>() {
-        return OwnedValue::from(number);
-    }
-    if let Ok(number) = value.parse::<f64>() {
-        return OwnedValue::from(number);
-    }
-    let string_value = sanitize_text(value.to_string());
-    OwnedValue::from(Str::from(string_value.as_str()))
-}
-
-fn yaml_value_to_owned_value(value: serde_yaml::Value) -> Result<OwnedValue> {
-    match value {
-        serde_yaml::Value::Bool(v) => Ok(OwnedValue::from(v)),
-        serde_yaml::Value::Number(v) => {
-            if let Some(i) = v.as_i64() {
-                Ok(OwnedValue::from(i))
-            } else if let Some(u) = v.as_u64() {
-                Ok(OwnedValue::from(u))
-            } else if let Some(f) = v.as_f64() {
-                Ok(OwnedValue::from(f))
-            } else {
-                Err(anyhow!("unsupported numeric hint value"))
-            }
-        }
-        serde_yaml::Value::String(v) => {
-            let value = sanitize_text(v);
-            Ok(OwnedValue::from(Str::from(value.as_str())))
-        }
-        serde_yaml::Value::Null => Ok(OwnedValue::from(Str::from(""))),
-        _ => Err(anyhow!(
-            "unsupported YAML hint type; only scalar values are allowed"
-        )),
-    }
-}
-
+/// Cleans text that the `sanitize` policy does not cover (ids, names, hints).
 fn sanitize_text(value: String) -> String {
-    value.replace('\0', "")
+    sanitize::clean(&value, Invisible::Strip)
 }
 
-async fn await_notification_result(
-    proxy: &Proxy<'_>,
-    id: u32,
-    print_id: bool,
-    await_timeout: Option<u64>,
-) -> Result<()> {
-    let mut action_stream = proxy
-        .receive_signal("ActionInvoked")
-        .await
-        .context("failed to subscribe to ActionInvoked signal")?;
-    let mut closed_stream = proxy
-        .receive_signal("NotificationClosed")
-        .await
-        .context("failed to subscribe to NotificationClosed signal")?;
-
-    let wait_future = async {
-        loop {
-            tokio::select! {
-                maybe_msg = action_stream.next() => {
-                    let msg = maybe_msg.context("action signal stream ended")?;
-                    let (signal_id, action_key): (u32, String) = msg.body().deserialize().context("failed to decode ActionInvoked")?;
-                    if signal_id == id {
-                        let parsed_action = serde_json::from_str::<serde_json::Value>(&action_key).ok();
-                        let output = if let Some(action_data) = parsed_action {
-                            if print_id {
-                                json!({"event":"action","id": id, "action_data": action_data})
-                            } else {
-                                json!({"event":"action","action_data": action_data})
-                            }
-                        } else if print_id {
-                            json!({"event":"action","id": id, "action": action_key})
-                        } else {
-                            json!({"event":"action","action": action_key})
-                        };
-                        println!("{}", output);
-                        return Ok(());
-                    }
-                }
-                maybe_msg = closed_stream.next() => {
-                    let msg = maybe_msg.context("closed signal stream ended")?;
-                    let (signal_id, reason): (u32, u32) = msg.body().deserialize().context("failed to decode NotificationClosed")?;
-                    if signal_id == id {
-                        let output = if print_id {
-                            json!({"event":"closed","id": id, "reason": reason})
-                        } else {
-                            json!({"event":"closed","reason": reason})
-                        };
-                        println!("{}", output);
-                        return Ok(());
-                    }
-                }
-            }
-        }
-    };
-
-    if let Some(ms) = await_timeout {
-        match tokio::time::timeout(Duration::from_millis(ms), wait_future).await {
-            Ok(result) => result,
-            Err(_) => {
-                let output = if print_id {
-                    json!({"event":"await-timeout","id": id, "timeout_ms": ms})
-                } else {
-                    json!({"event":"await-timeout","timeout_ms": ms})
-                };
-                println!("{}", output);
-                Err(AwaitTimeoutError { timeout_ms: ms }.into())
-            }
-        }
-    } else {
-        wait_future.await
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn relative_paths_follow_the_payload_file() {
+        let mut payloads = vec![
+            serde_yaml::from_str(
+                "image: a.png\nbody_file: /abs/body.txt\nsound: bell.oga\nsounds: {low: complete, critical: file://alarm.oga}",
+            )
+            .unwrap(),
+            serde_yaml::from_str("sound: message-new-instant\nimage: '-'").unwrap(),
+        ];
+        resolve_relative_paths(&mut payloads, Path::new("/payloads/deploy.yaml"));
+        let expected: Vec<serde_yaml::Value> = vec![
+            serde_yaml::from_str(
+                "image: /payloads/a.png\nbody_file: /abs/body.txt\nsound: /payloads/bell.oga\nsounds: {low: complete, critical: /payloads/alarm.oga}",
+            )
+            .unwrap(),
+            serde_yaml::from_str("sound: message-new-instant\nimage: '-'").unwrap(),
+        ];
+        assert_eq!(payloads, expected);
+
+        let mut stdin = vec![serde_yaml::from_str("sound: bell.oga").unwrap()];
+        resolve_relative_paths(&mut stdin, Path::new("-"));
+        assert_eq!(stdin[0]["sound"], "bell.oga");
     }
 }
@@ -0,0 +1,436 @@
+//! Body formats. Servers advertising `body-markup` parse the body as a small
+//! XML-like subset (`b`, `i`, `u`, `a href`, `img src alt`), so untrusted plain
+//! text has to be escaped, markup has to be cut down to that subset and
+//! Markdown is converted into it. Images would have the server load any
+//! remote URL or local file, so they are only kept when allowed.
+
+use clap::ValueEnum;
+use pulldown_cmark::{Event, Options, Parser, Tag, TagEnd};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum BodyFormat {
+    /// literal text; `&<>` are escaped for servers that parse markup
+    Plain,
+    /// the spec's markup subset; other tags and attributes are escaped
+    Markup,
+    /// Markdown, converted to the markup subset or to clean plain text (links as "text (url)")
+    Markdown,
+}
+
+impl BodyFormat {
+    /// The body as sent to a server with (`markup`) or without `body-markup`;
+    /// `images` keeps markup `img` tags instead of their alt text.
+    pub(crate) fn render(self, body: &str, markup: bool, images: bool) -> String {
+        match (self, markup) {
+            (Self::Plain, true) => escape(body),
+            (Self::Plain, false) => body.to_string(),
+            (Self::Markup, true) => sanitize(body, images),
+            (Self::Markup, false) => strip(&sanitize(body, images)),
+            (Self::Markdown, true) => markdown_to_markup(body),
+            (Self::Markdown, false) => strip(&markdown_to_markup(body)),
+        }
+    }
+}
+
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+const LINK_SCHEMES: [&str; 3] = ["http://", "https://", "mailto:"];
+
+struct MarkupTag<'a> {
+    name: String,
+    closing: bool,
+    attributes: Vec<(String, &'a str)>,
+}
+
+/// Keeps `b`, `i`, `u`, `a href` (http, https and mailto only) and, with
+/// `images`, `img src alt`; without it an image becomes its alt text. Every
+/// other tag, attribute, stray `<`/`>` and unknown entity is escaped. Tags are
+/// balanced so strict (GMarkup) parsers accept the result.
+pub(crate) fn sanitize(markup: &str, images: bool) -> String {
+    let mut output = String::with_capacity(markup.len());
+    let mut open = Vec::<String>::new();
+    let mut rest = markup;
+
+    while let Some(character) = rest.chars().next() {
+        match character {
+            '<' => {
+                let Some((tag, length)) = parse_tag(rest) else {
+                    output.push_str("&lt;");
+                    rest = &rest[1..];
+                    continue;
+                };
+                let raw = &rest[..length];
+                rest = &rest[length..];
+                match (tag.name.as_str(), tag.closing) {
+                    ("b" | "i" | "u", false) => {
+                        output.push_str(&format!("<{}>", tag.name));
+                        open.push(tag.name);
+                    }
+                    ("a", false) => {
+                        match tag.attribute("href").filter(|href| is_safe_link(href)) {
+                            Some(href) => output.push_str(&format!("<a href=\"{}\">", escape(&decode(href)))),
+                            None => output.push_str("<a>"),
+                        }
+                        open.push(tag.name);
+                    }
+                    ("img", false) if !images => {
+                        if let Some(alt) = tag.attribute("alt") {
+                            output.push_str(&escape(&decode(alt)));
+                        }
+                    }
+                    ("img", false) => {
+                        output.push_str("<img");
+                        for name in ["src", "alt"] {
+                            if let Some(value) = tag.attribute(name) {
+                                output.push_str(&format!(" {name}=\"{}\"", escape(&decode(value))));
+                            }
+                        }
+                        output.push_str("/>");
+                    }
+                    (name, true) if open.iter().any(|open| open == name) => {
+                        while let Some(inner) = open.pop() {
+                            output.push_str(&format!("</{inner}>"));
+                            if inner == name {
+                                break;
+                            }
+                        }
+                    }
+                    (_, true) if ["b", "i", "u", "a", "img"].contains(&tag.name.as_str()) => {}
+                    _ => output.push_str(&escape(raw)),
+                }
+            }
+            '>' => {
+                output.push_str("&gt;");
+                rest = &rest[1..];
+            }
+            '&' => match entity_length(rest) {
+                Some(length) => {
+                    output.push_str(&rest[..length]);
+                    rest = &rest[length..];
+                }
+                None => {
+                    output.push_str("&amp;");
+                    rest = &rest[1..];
+                }
+            },
+            _ => {
+                output.push(character);
+                rest = &rest[character.len_utf8()..];
+            }
+        }
+    }
+
+    while let Some(inner) = open.pop() {
+        output.push_str(&format!("</{inner}>"));
+    }
+    output
+}
+
+/// Converts emphasis, links, code, headings and lists to the markup subset.
+/// Raw HTML in the Markdown is shown as text; images leave their alt text.
+pub(crate) fn markdown_to_markup(markdown: &str) -> String {
+    let options = Options::ENABLE_STRIKETHROUGH | Options::ENABLE_TASKLISTS;
+    let mut output = String::with_capacity(markdown.len());
+    // Next number of each open list, `None` for bullet lists.
+    let mut lists = Vec::<Option<u64>>::new();
+    // Closing tag of each open link; unsafe links are dropped, keeping their text.
+    let mut links = Vec::<&str>::new();
+
+    for event in Parser::new_ext(markdown, options) {
+        match event {
+            Event::Start(Tag::Strong) => output.push_str("<b>"),
+            Event::End(TagEnd::Strong) => output.push_str("</b>"),
+            Event::Start(Tag::Emphasis) => output.push_str("<i>"),
+            Event::End(TagEnd::Emphasis) => output.push_str("</i>"),
+            Event::Start(Tag::Heading { .. }) => output.push_str("<b>"),
+            Event::End(TagEnd::Heading(_)) => {
+                output.push_str("</b>");
+                end_block(&mut output, &lists);
+            }
+            Event::Start(Tag::Link { dest_url, .. }) => {
+                if is_safe_link(&dest_url) {
+                    output.push_str(&format!("<a href=\"{}\">", escape(&dest_url)));
+                    links.push("</a>");
+                } else {
+                    links.push("");
+                }
+            }
+            Event::End(TagEnd::Link) => output.push_str(links.pop().unwrap_or_default()),
+            Event::Start(Tag::List(start)) => {
+                if !lists.is_empty() && !output.ends_with('\n') {
+                    output.push('\n');
+                }
+                lists.push(start);
+            }
+            Event::End(TagEnd::List(_)) => {
+                lists.pop();
+                if lists.is_empty() {
+                    end_block(&mut output, &lists);
+                }
+            }
+            Event::Start(Tag::Item) => {
+                output.push_str(&"  ".repeat(lists.len().saturating_sub(1)));
+                match lists.last_mut() {
+                    Some(Some(number)) => {
+                        output.push_str(&format!("{number}. "));
+                        *number += 1;
+                    }
+                    _ => output.push_str("• "),
+                }
+            }
+            Event::End(TagEnd::Item) => {
+                let trimmed = output.trim_end_matches('\n').len();
+                output.truncate(trimmed);
+                output.push('\n');
+            }
+            Event::End(
+                TagEnd::Paragraph | TagEnd::CodeBlock | TagEnd::BlockQuote(_) | TagEnd::HtmlBlock,
+            ) => {
+                end_block(&mut output, &lists);
+            }
+            Event::Text(text) | Event::Code(text) | Event::Html(text) | Event::InlineHtml(text) => {
+                output.push_str(&escape(&text));
+            }
+            Event::SoftBreak | Event::HardBreak => output.push('\n'),
+            Event::Rule => output.push_str("――――――――\n\n"),
+            Event::TaskListMarker(checked) => output.push_str(if checked { "☑ " } else { "☐ " }),
+            _ => {}
+        }
+    }
+
+    output.trim_end().to_string()
+}
+
+/// Blocks are separated by a blank line, except inside list items.
+fn end_block(output: &mut String, lists: &[Option<u64>]) {
+    let trimmed = output.trim_end_matches('\n').len();
+    output.truncate(trimmed);
+    output.push_str(if lists.is_empty() { "\n\n" } else { "\n" });
+}
+
+/// Plain text from sanitized markup: tags are dropped (images leave their alt
+/// text, links add their target as `text (url)`) and entities decoded.
+pub(crate) fn strip(markup: &str) -> String {
+    let mut text = String::with_capacity(markup.len());
+    // Target and text start of each open link.
+    let mut links = Vec::<(Option<String>, usize)>::new();
+    let mut rest = markup;
+    while let Some(start) = rest.find('<') {
+        text.push_str(&decode(&rest[..start]));
+        let Some((tag, length)) = parse_tag(&rest[start..]) else {
+            text.push('<');
+            rest = &rest[start + 1..];
+            continue;
+        };
+        match (tag.name.as_str(), tag.closing) {
+            ("img", false) => {
+                if let Some(alt) = tag.attribute("alt") {
+                    text.push_str(&decode(alt));
+                }
+            }
+            ("a", false) => links.push((tag.attribute("href").map(decode), text.len())),
+            ("a", true) => {
+                if let Some((Some(href), text_start)) = links.pop() {
+                    let label = text[text_start..].trim();
+                    // Autolinks already show their target.
+                    if label != href && href.strip_prefix("mailto:") != Some(label) {
+                        text.push_str(&format!(" ({href})"));
+                    }
+                }
+            }
+            _ => {}
+        }
+        rest = &rest[start + length..];
+    }
+    text.push_str(&decode(rest));
+    text
+}
+
+impl MarkupTag<'_> {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| *value)
+    }
+}
+
+/// Parses `<name attr="value" ...>`, `</name>` or `<name .../>` at the start of
+/// `input`, returning the tag and its length in bytes.
+fn parse_tag(input: &str) -> Option<(MarkupTag<'_>, usize)> {
+    let mut rest = input.strip_prefix('<')?;
+    let closing = rest.starts_with('/');
+    if closing {
+        rest = &rest[1..];
+    }
+    let name_length = rest
+        .find(|character: char| !character.is_ascii_alphanumeric())
+        .unwrap_or(rest.len());
+    if name_length == 0 {
+        return None;
+    }
+    let name = rest[..name_length].to_ascii_lowercase();
+    rest = &rest[name_length..];
+
+    let mut attributes = Vec::new();
+    loop {
+        let trimmed = rest.trim_start();
+        if let Some(after) = trimmed.strip_prefix("/>").or_else(|| trimmed.strip_prefix('>')) {
+            let length = input.len() - after.len();
+            return Some((MarkupTag { name, closing, attributes }, length));
+        }
+        if closing || trimmed.len() == rest.len() {
+            return None;
+        }
+        let key_length = trimmed
+            .find(|character: char| !(character.is_ascii_alphanumeric() || character == '-'))
+            .unwrap_or(trimmed.len());
+        if key_length == 0 {
+            return None;
+        }
+        let key = trimmed[..key_length].to_ascii_lowercase();
+        let after_key = trimmed[key_length..].trim_start().strip_prefix('=')?.trim_start();
+        let quote = after_key.chars().next().filter(|quote| matches!(quote, '"' | '\''))?;
+        let end = after_key[1..].find(quote)?;
+        attributes.push((key, &after_key[1..1 + end]));
+        rest = &after_key[end + 2..];
+    }
+}
+
+fn is_safe_link(href: &str) -> bool {
+    let href = decode(href);
+    let href = href.trim().to_ascii_lowercase();
+    LINK_SCHEMES.iter().any(|scheme| href.starts_with(scheme))
+}
+
+/// Length of a well-formed entity (`&amp;`, `&#60;`, `&#x3c;`) at the start of `input`.
+fn entity_length(input: &str) -> Option<usize> {
+    let end = input[1..].find(';')? + 1;
+    decode_entity(&input[1..end])?;
+    Some(end + 1)
+}
+
+fn decode_entity(entity: &str) -> Option<char> {
+    match entity {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => {
+            let number = entity.strip_prefix('#')?;
+            let code = match number.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => number.parse().ok()?,
+            };
+            char::from_u32(code).filter(|character| *character != '\0')
+        }
+    }
+}
+
+fn decode(text: &str) -> String {
+    let mut decoded = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+        match entity_length(rest) {
+            Some(length) => {
+                decoded.extend(decode_entity(&rest[1..length - 1]));
+                rest = &rest[length..];
+            }
+            None => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+    decoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plain(markdown: &str) -> String {
+        BodyFormat::Markdown.render(markdown, false, false)
+    }
+
+    #[test]
+    fn markdown_inline_formatting() {
+        assert_eq!(markdown_to_markup("**bold** *it* `a<b`"), "<b>bold</b> <i>it</i> a&lt;b");
+        assert_eq!(markdown_to_markup("# Title\n\ntext"), "<b>Title</b>\n\ntext");
+        assert_eq!(markdown_to_markup("one\ntwo\n\nthree"), "one\ntwo\n\nthree");
+        assert_eq!(markdown_to_markup("a\n\n---\n\nb"), "a\n\n――――――――\n\nb");
+        // Raw HTML and images are text.
+        assert_eq!(markdown_to_markup("<script>x</script>"), "&lt;script&gt;x&lt;/script&gt;");
+        assert_eq!(markdown_to_markup("![a chart](https://x.org/c.png)"), "a chart");
+    }
+
+    #[test]
+    fn markdown_links() {
+        assert_eq!(
+            markdown_to_markup("[docs](https://x.org/?a=1&b=2)"),
+            "<a href=\"https://x.org/?a=1&amp;b=2\">docs</a>"
+        );
+        assert_eq!(markdown_to_markup("[run](javascript:alert(1))"), "run");
+        assert_eq!(markdown_to_markup("[file](file:///etc/passwd)"), "file");
+    }
+
+    #[test]
+    fn markdown_lists() {
+        assert_eq!(markdown_to_markup("- a\n- b\n\nafter"), "• a\n• b\n\nafter");
+        assert_eq!(markdown_to_markup("3. c\n4. d"), "3. c\n4. d");
+        assert_eq!(markdown_to_markup("- a\n  1. x\n  2. y\n- b"), "• a\n  1. x\n  2. y\n• b");
+        assert_eq!(markdown_to_markup("- [x] done\n- [ ] todo"), "• ☑ done\n• ☐ todo");
+    }
+
+    #[test]
+    fn plain_text_keeps_link_targets() {
+        assert_eq!(plain("see [docs](https://x.org/a?b=1&c=2)"), "see docs (https://x.org/a?b=1&c=2)");
+        assert_eq!(plain("<https://x.org>"), "https://x.org");
+        assert_eq!(plain("[mail me](mailto:a@b.org)"), "mail me (mailto:a@b.org)");
+        assert_eq!(plain("[bad](javascript:x) **b** &amp; `<i>`"), "bad b & <i>");
+        assert_eq!(
+            BodyFormat::Markup.render(r#"<a href="https://x.org">site</a> <a>bare</a> 1 &lt; 2"#, false, false),
+            "site (https://x.org) bare 1 < 2"
+        );
+    }
+
+    #[test]
+    fn markup_is_cut_to_the_subset() {
+        assert_eq!(sanitize("<b>a<i>b</b>c", false), "<b>a<i>b</i></b>c");
+        assert_eq!(sanitize("<u>open", false), "<u>open</u>");
+        assert_eq!(sanitize("<span style='x'>s</span>", false), "&lt;span style='x'&gt;s&lt;/span&gt;");
+        assert_eq!(sanitize(r#"<a href="javascript:x">j</a>"#, false), "<a>j</a>");
+        assert_eq!(sanitize("1 < 2 && 3 > 2 &amp; &#x41; &bogus;", false), "1 &lt; 2 &amp;&amp; 3 &gt; 2 &amp; &#x41; &amp;bogus;");
+        assert_eq!(escape(r#"<"&">"#), "&lt;&quot;&amp;&quot;&gt;");
+    }
+
+    #[test]
+    fn images_need_permission() {
+        let markup = r#"<img src="https://tracker.example/p.png" alt="chart &amp; legend"/> <b>done</b>"#;
+        assert_eq!(sanitize(markup, false), "chart &amp; legend <b>done</b>");
+        assert_eq!(
+            sanitize(markup, true),
+            r#"<img src="https://tracker.example/p.png" alt="chart &amp; legend"/> <b>done</b>"#
+        );
+        assert_eq!(sanitize(r#"<img src="file:///etc/passwd">x"#, false), "x");
+    }
+}
@@ -0,0 +1,175 @@
+//! Delivery through terminal escape sequences written to the controlling tty.
+//!
+//! Useful over SSH with no D-Bus: the user's local terminal emulator raises the
+//! notification. Terminals cannot report actions back, so `--await` is unsupported.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use crate::backend::Delivery;
+use crate::tty::TTY_PATH;
+use crate::{Request, Urgency, generate_notification_id};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum, JsonSchema)]
+pub(crate) enum OscSequence {
+    /// OSC 9 (iTerm2, WezTerm, Windows Terminal)
+    #[value(name = "9")]
+    #[serde(rename = "9")]
+    Osc9,
+    /// OSC 777 notify (urxvt, foot, Ghostty, VTE)
+    #[value(name = "777")]
+    #[serde(rename = "777")]
+    Osc777,
+    /// OSC 99 (kitty)
+    #[value(name = "99")]
+    #[serde(rename = "99")]
+    Osc99,
+}
+
+impl OscSequence {
+    fn detect() -> Self {
+        let term = std::env::var("TERM").unwrap_or_default();
+        let term_program = std::env::var("TERM_PROGRAM").unwrap_or_default();
+        if term == "xterm-kitty" || std::env::var_os("KITTY_WINDOW_ID").is_some() {
+            Self::Osc99
+        } else if term.contains("rxvt")
+            || term.starts_with("foot")
+            || term == "xterm-ghostty"
+            || term_program == "ghostty"
+            || std::env::var_os("VTE_VERSION").is_some()
+        {
+            Self::Osc777
+        } else {
+            Self::Osc9
+        }
+    }
+}
+
+pub(crate) fn deliver(request: &Request) -> Result<Delivery> {
+    if request.await_result {
+        bail!("--await is not supported by the osc backend");
+    }
+
+    let notification_id = if request.replaces_id != 0 {
+        request.replaces_id
+    } else {
+        generate_notification_id()
+    };
+
+    let sequence = request.osc_sequence.unwrap_or_else(OscSequence::detect);
+    let title = sanitize_osc_text(&request.summary);
+    let body = sanitize_osc_text(&request.render_body(false));
+    let output = render_sequence(sequence, notification_id, request.urgency, &title, &body);
+    let output = if std::env::var_os("TMUX").is_some() {
+        wrap_tmux_passthrough(&output)
+    } else {
+        output
+    };
+
+    let mut tty = OpenOptions::new()
+        .write(true)
+        .open(TTY_PATH)
+        .context("failed to open controlling terminal")?;
+    tty.write_all(output.as_bytes())
+        .and_then(|_| tty.flush())
+        .context("failed to write terminal notification")?;
+
+    Ok(Delivery {
+        id: notification_id,
+        pending: None,
+    })
+}
+
+fn render_sequence(
+    sequence: OscSequence,
+    id: u32,
+    urgency: Urgency,
+    title: &str,
+    body: &str,
+) -> String {
+    match sequence {
+        OscSequence::Osc9 => {
+            let text = match (title.is_empty(), body.is_empty()) {
+                (false, false) => format!("{title}: {body}"),
+                (false, true) => title.to_string(),
+                _ => body.to_string(),
+            };
+            // ConEmu treats `9;<digits>;` as a sub-command (progress, cwd, ...).
+            let text = if text.starts_with(|c: char| c.is_ascii_digit()) {
+                format!(" {text}")
+            } else {
+                text
+            };
+            format!("\x1b]9;{text}\x07")
+        }
+        OscSequence::Osc777 => {
+            let title = title.replace(';', ",");
+            format!("\x1b]777;notify;{title};{body}\x07")
+        }
+        OscSequence::Osc99 => {
+            let urgency = urgency.as_hint_value();
+            let mut output = format!("\x1b]99;i={id}:d=0:u={urgency}:p=title;{title}\x1b\\");
+            output.push_str(&format!("\x1b]99;i={id}:d=1:p=body;{body}\x1b\\"));
+            output
+        }
+    }
+}
+
+fn wrap_tmux_passthrough(sequence: &str) -> String {
+    format!("\x1bPtmux;{}\x1b\\", sequence.replace('\x1b', "\x1b\x1b"))
+}
+
+/// Strips C0/C1 controls (ESC, BEL, ST, ...) so text cannot terminate the
+/// sequence early or inject its own; newlines and tabs become spaces.
+fn sanitize_osc_text(value: &str) -> String {
+    value
+        .chars()
+        .filter_map(|character| match character {
+            '\n' | '\r' | '\t' => Some(' '),
+            character if character.is_control() => None,
+            character => Some(character),
+        })
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitize_removes_terminators() {
+        assert_eq!(sanitize_osc_text("a\x07b\x1b\\c\u{9c}d"), "ab\\cd");
+        assert_eq!(sanitize_osc_text("\x1b]9;evil\x07"), "]9;evil");
+        assert_eq!(sanitize_osc_text(" line\none\ttab\r\n"), "line one tab");
+        assert_eq!(sanitize_osc_text("café ✓"), "café ✓");
+    }
+
+    #[test]
+    fn tmux_passthrough_doubles_escapes() {
+        assert_eq!(wrap_tmux_passthrough("\x1b]9;hi\x07"), "\x1bPtmux;\x1b\x1b]9;hi\x07\x1b\\");
+        assert_eq!(
+            wrap_tmux_passthrough("\x1b]99;p=title;t\x1b\\"),
+            "\x1bPtmux;\x1b\x1b]99;p=title;t\x1b\x1b\\\x1b\\"
+        );
+    }
+
+    #[test]
+    fn sequences() {
+        let render = |sequence, title, body| render_sequence(sequence, 7, Urgency::Critical, title, body);
+        assert_eq!(render(OscSequence::Osc9, "Build", "done"), "\x1b]9;Build: done\x07");
+        assert_eq!(render(OscSequence::Osc9, "", "done"), "\x1b]9;done\x07");
+        assert_eq!(render(OscSequence::Osc9, "4 tests", ""), "\x1b]9; 4 tests\x07");
+        assert_eq!(render(OscSequence::Osc777, "a;b", "c;d"), "\x1b]777;notify;a,b;c;d\x07");
+        assert_eq!(
+            render(OscSequence::Osc99, "T", "B"),
+            "\x1b]99;i=7:d=0:u=2:p=title;T\x1b\\\x1b]99;i=7:d=1:p=body;B\x1b\\"
+        );
+    }
+}
@@ -0,0 +1,158 @@
+//! Delivery through the XDG desktop portal (`org.freedesktop.portal.Notification`).
+//!
+//! Inside Flatpak sandboxes `org.freedesktop.Notifications` is often filtered;
+//! the portal is the supported path there. The portal has no `NotificationClosed`
+//! signal, so `--await` only resolves on an action or the await cap.
+
+use std::collections::HashMap;
+
+use anyhow::{Context, Result};
+use futures_util::StreamExt;
+use zbus::{Proxy, proxy::SignalStream};
+use zvariant::{OwnedValue, Value};
+
+use crate::backend::{Delivery, EventReporter, Pending as BackendPending};
+use crate::dbus::Connections;
+use crate::{Request, Urgency, generate_notification_id};
+
+const PORTAL_DEST: &str = "org.freedesktop.portal.Desktop";
+const PORTAL_PATH: &str = "/org/freedesktop/portal/desktop";
+const PORTAL_IFACE: &str = "org.freedesktop.portal.Notification";
+
+pub(crate) async fn deliver(request: &Request, connections: &Connections) -> Result<Delivery> {
+    let connection = connections.get(&request.bus).await?;
+
+    let proxy = Proxy::new(&connection, PORTAL_DEST, PORTAL_PATH, PORTAL_IFACE)
+        .await
+        .context("failed to create notification portal proxy")?;
+
+    let notification_id = if request.replaces_id != 0 {
+        request.replaces_id
+    } else {
+        generate_notification_id()
+    };
+    let portal_id = portal_id(notification_id);
+
+    // Subscribe before sending so a fast click is not missed.
+    let action_stream = if request.await_result {
+        Some(
+            proxy
+                .receive_signal("ActionInvoked")
+                .await
+                .context("failed to subscribe to portal ActionInvoked signal")?,
+        )
+    } else {
+        None
+    };
+
+    let notification = build_notification(request)?;
+    proxy
+        .call_method("AddNotification", &(portal_id.as_str(), notification))
+        .await
+        .context("failed to send portal notification")?;
+
+    let pending = action_stream.map(|action_stream| {
+        BackendPending::Portal(Box::new(Pending {
+            proxy,
+            portal_id,
+            action_stream,
+        }))
+    });
+
+    Ok(Delivery {
+        id: notification_id,
+        pending,
+    })
+}
+
+pub(crate) struct Pending {
+    proxy: Proxy<'static>,
+    portal_id: String,
+    action_stream: SignalStream<'static>,
+}
+
+impl Pending {
+    pub(crate) async fn wait(&mut self, events: &EventReporter) -> Result<()> {
+        loop {
+            let msg = self
+                .action_stream
+                .next()
+                .await
+                .context("portal action signal stream ended")?;
+            let (signal_id, action_key, _parameter): (String, String, Vec<OwnedValue>) = msg
+                .body()
+                .deserialize()
+                .context("failed to decode portal ActionInvoked")?;
+            if signal_id == self.portal_id {
+                events.action(action_key);
+                return Ok(());
+            }
+        }
+    }
+
+    /// The portal keeps notifications until removed; clean up once we are done waiting.
+    pub(crate) async fn finish(self) {
+        let _ = self
+            .proxy
+            .call_method("RemoveNotification", &(self.portal_id.as_str(),))
+            .await;
+    }
+}
+
+fn build_notification(request: &Request) -> Result<HashMap<&'static str, Value<'static>>> {
+    let mut notification = HashMap::new();
+    notification.insert("title", Value::from(request.summary.clone()));
+    notification.insert("body", Value::from(request.render_body(false)));
+    notification.insert("priority", Value::from(portal_priority(request.urgency)));
+
+    if !request.icon.is_empty() {
+        notification.insert("icon", portal_icon(&request.icon)?);
+    }
+
+    let mut buttons = Vec::<HashMap<&'static str, Value<'static>>>::new();
+    for pair in request.actions.chunks(2) {
+        let [id, label] = pair else { continue };
+        if id == "default" {
+            notification.insert("default-action", Value::from(id.clone()));
+            continue;
+        }
+        let mut button = HashMap::new();
+        button.insert("label", Value::from(label.clone()));
+        button.insert("action", Value::from(id.clone()));
+        buttons.push(button);
+    }
+    if !buttons.is_empty() {
+        notification.insert("buttons", Value::from(buttons));
+    }
+
+    Ok(notification)
+}
+
+fn portal_priority(urgency: Urgency) -> &'static str {
+    match urgency {
+        Urgency::Low => "low",
+        Urgency::Normal => "normal",
+        Urgency::Critical => "urgent",
+    }
+}
+
+/// Serializes an icon as a `GIcon` variant: file paths are sent as bytes, since
+/// the portal may not be able to read paths inside the sandbox; anything else is
+/// treated as a themed icon name.
+fn portal_icon(icon: &str) -> Result<Value<'static>> {
+    let path = icon.strip_prefix("file://").unwrap_or(icon);
+    if path.starts_with('/') {
+        let bytes = std::fs::read(path)
+            .with_context(|| format!("failed to read icon file: {path}"))?;
+        Ok(Value::from(("bytes".to_string(), Value::from(bytes))))
+    } else {
+        Ok(Value::from((
+            "themed".to_string(),
+            Value::from(vec![icon.to_string()]),
+        )))
+    }
+}
+
+fn portal_id(id: u32) -> String {
+    format!("notify-{id}")
+}
@@ -0,0 +1,456 @@
+//! Scheduled delivery (`--at`, `--in`, `deliver_at`). Requests that are
+//! awaited or print their id wait in process. Others are stored under the
+//! state directory, due time first in the file name, and delivered by a
+//! detached `notify scheduled run`, which exits once nothing is pending. The
+//! files survive reboots; the next `notify` that sends restarts the scheduler.
+
+use std::fs::OpenOptions;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, anyhow, bail};
+use jiff::civil::{DateTime, Time};
+use jiff::tz::TimeZone;
+use jiff::{Timestamp, Zoned};
+use serde::{Deserialize, Serialize};
+
+use crate::backend;
+use crate::dbus::Connections;
+use crate::{hex, spool};
+use crate::{Request, state_dir};
+
+const SCHEDULE_VERSION: u32 = 1;
+
+/// Longest sleep between looks at the clock and the directory, so cancels,
+/// earlier new items and suspend are noticed.
+const POLL: Duration = Duration::from_secs(1);
+
+/// Delivery attempts before an item that keeps failing is moved aside.
+const MAX_ATTEMPTS: u32 = 8;
+
+/// Wait after the first failed attempt; doubled after each further one.
+const RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Longest wait between attempts.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(15 * 60);
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ScheduledEntry<R> {
+    version: u32,
+    created: u64,
+    /// failed delivery attempts so far
+    #[serde(default)]
+    attempts: u32,
+    request: R,
+}
+
+struct Item {
+    id: String,
+    due: u64,
+    path: PathBuf,
+}
+
+fn schedule_dir() -> Result<PathBuf> {
+    Ok(state_dir()?.join("notify").join("scheduled"))
+}
+
+pub(crate) fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Unix time in milliseconds for `HH:MM[:SS]` (today, or tomorrow once
+/// passed), `YYYY-MM-DD[ HH:MM[:SS]]` in local time, RFC 3339 or `@SECONDS`.
+pub(crate) fn parse_time(text: &str) -> Result<u64> {
+    let text = text.trim();
+    let now = Zoned::now();
+    let timestamp = resolve_time(text, &now)?;
+    if timestamp <= now.timestamp() {
+        bail!("time '{text}' is in the past");
+    }
+    Ok(timestamp.as_millisecond() as u64)
+}
+
+/// Whether `text` is a time `parse_time` understands, past or not.
+pub(crate) fn check_time(text: &str) -> Result<()> {
+    resolve_time(text.trim(), &Zoned::now()).map(drop)
+}
+
+fn resolve_time(text: &str, now: &Zoned) -> Result<Timestamp> {
+    let time_zone = now.time_zone().clone();
+    let timestamp = if let Some(seconds) = text.strip_prefix('@') {
+        let seconds = seconds
+            .parse::<i64>()
+            .with_context(|| format!("invalid unix time '{text}'"))?;
+        Timestamp::from_second(seconds).with_context(|| format!("invalid unix time '{text}'"))?
+    } else if let Ok(timestamp) = text.parse::<Timestamp>() {
+        timestamp
+    } else if let Ok(datetime) = text.parse::<DateTime>() {
+        datetime
+            .to_zoned(time_zone)
+            .with_context(|| format!("invalid local time '{text}'"))?
+            .timestamp()
+    } else if let Ok(time) = text.parse::<Time>() {
+        let today = now
+            .date()
+            .to_datetime(time)
+            .to_zoned(time_zone.clone())
+            .with_context(|| format!("invalid local time '{text}'"))?;
+        if today.timestamp() > now.timestamp() {
+            today.timestamp()
+        } else {
+            now.date()
+                .tomorrow()?
+                .to_datetime(time)
+                .to_zoned(time_zone)
+                .with_context(|| format!("invalid local time '{text}'"))?
+                .timestamp()
+        }
+    } else {
+        bail!("invalid time '{text}'; use e.g. 14:30, '2026-03-01 09:00', 2026-03-01T09:00:00Z or @1767225600");
+    };
+    Ok(timestamp)
+}
+
+/// Local time for listings and event output.
+pub(crate) fn format_time(unix_ms: u64) -> String {
+    match Timestamp::from_millisecond(unix_ms as i64) {
+        Ok(timestamp) => timestamp
+            .to_zoned(TimeZone::system())
+            .strftime("%Y-%m-%d %H:%M:%S %:z")
+            .to_string(),
+        Err(_) => format!("@{}", unix_ms / 1000),
+    }
+}
+
+/// Sleeps until the wall clock reaches `unix_ms`, also across suspend.
+pub(crate) async fn sleep_until(unix_ms: u64) {
+    loop {
+        let remaining = unix_ms.saturating_sub(now_ms());
+        if remaining == 0 {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(remaining).min(POLL)).await;
+    }
+}
+
+/// Stores the fully merged request, starts the scheduler if it is not running
+/// and returns the id for `notify scheduled cancel`.
+pub(crate) fn add(request: &Request, due: u64) -> Result<String> {
+    let dir = schedule_dir()?;
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("failed to create schedule directory: {}", dir.display()))?;
+
+    let mut random = [0_u8; 4];
+    getrandom::fill(&mut random).map_err(|error| anyhow!("failed to generate schedule id: {error}"))?;
+    let id = hex::encode(&random);
+    let entry = ScheduledEntry {
+        version: SCHEDULE_VERSION,
+        created: now_ms() / 1000,
+        attempts: 0,
+        request,
+    };
+    write_entry(&dir, due, &id, &entry)?;
+
+    start_scheduler(&dir)?;
+    Ok(id)
+}
+
+fn write_entry(dir: &Path, due: u64, id: &str, entry: &ScheduledEntry<&Request>) -> Result<PathBuf> {
+    // Zero-padded due time keeps lexical order equal to delivery order.
+    let name = format!("{due:020}-{id}.json");
+    let temp_path = dir.join(format!(".{name}.tmp"));
+    let path = dir.join(name);
+    let contents = serde_json::to_vec_pretty(entry).context("failed to serialize scheduled notification")?;
+    std::fs::write(&temp_path, contents)
+        .with_context(|| format!("failed to write scheduled notification: {}", temp_path.display()))?;
+    std::fs::rename(&temp_path, &path)
+        .with_context(|| format!("failed to write scheduled notification: {}", path.display()))?;
+    Ok(path)
+}
+
+/// Starts the scheduler when notifications are pending but none is running,
+/// e.g. after a reboot or logout. Called only by invocations that deliver;
+/// failures are only warned about.
+pub(crate) fn resume() {
+    // Without a state directory nothing can have been scheduled.
+    let Ok(dir) = schedule_dir() else {
+        return;
+    };
+    let resume = || -> Result<()> {
+        if list_items()?.is_empty() {
+            return Ok(());
+        }
+        let lock_path = dir.join(".lock");
+        let lock = spool::open_lock(&lock_path)?;
+        if !spool::try_lock(&lock, &lock_path)? {
+            return Ok(());
+        }
+        // Released before the scheduler takes it; should another `notify`
+        // start one meanwhile, the later of the two exits.
+        drop(lock);
+        start_scheduler(&dir)
+    };
+    if let Err(error) = resume() {
+        eprintln!("warning: failed to resume scheduled notifications: {error:#}");
+    }
+}
+
+/// Runs `notify scheduled run` detached, logging to `scheduler.log`. A
+/// scheduler that is already running keeps the lock and the new one exits.
+fn start_scheduler(dir: &Path) -> Result<()> {
+    let executable = std::env::current_exe().context("failed to locate the notify executable")?;
+    let log_path = dir.join("scheduler.log");
+    let log = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&log_path)
+        .with_context(|| format!("failed to open scheduler log: {}", log_path.display()))?;
+    Command::new(executable)
+        .args(["scheduled", "run"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(log)
+        .process_group(0)
+        .spawn()
+        .context("failed to start the scheduler")?;
+    Ok(())
+}
+
+/// `notify scheduled list`: pending notifications, next due first.
+pub(crate) fn list() -> Result<()> {
+    let items = list_items()?;
+    if items.is_empty() {
+        return Ok(());
+    }
+    println!("{:<8}  {:<25}  SUMMARY", "ID", "DUE");
+    for item in items {
+        let summary = read_entry(&item.path)
+            .map(|entry| entry.request.summary)
+            .unwrap_or_else(|error| format!("({error:#})"));
+        println!("{:<8}  {:<25}  {summary}", item.id, format_time(item.due));
+    }
+    Ok(())
+}
+
+/// `notify scheduled cancel`: removes the given ids, or everything with `all`.
+pub(crate) fn cancel(ids: &[String], all: bool) -> Result<()> {
+    let items = list_items()?;
+    if all {
+        for item in &items {
+            remove_item(item)?;
+        }
+        return Ok(());
+    }
+    if ids.is_empty() {
+        bail!("name the scheduled notifications to cancel, or use --all");
+    }
+    for id in ids {
+        let item = items
+            .iter()
+            .find(|item| item.id == *id)
+            .ok_or_else(|| anyhow!("no scheduled notification '{id}' (see 'notify scheduled list')"))?;
+        remove_item(item)?;
+    }
+    Ok(())
+}
+
+/// `notify scheduled run`: delivers pending notifications when due and exits
+/// once none are left. Only one scheduler runs at a time.
+pub(crate) async fn run() -> Result<()> {
+    let dir = schedule_dir()?;
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("failed to create schedule directory: {}", dir.display()))?;
+    let lock_path = dir.join(".lock");
+    let lock = spool::open_lock(&lock_path)?;
+    if !spool::try_lock(&lock, &lock_path)? {
+        return Ok(());
+    }
+
+    loop {
+        let Some(item) = list_items()?.into_iter().next() else {
+            // Unlock before the final look, so an item added meanwhile either
+            // shows up here or finds the lock free for its own scheduler.
+            lock.unlock()
+                .with_context(|| format!("failed to release scheduler lock: {}", lock_path.display()))?;
+            if list_items()?.is_empty() || !spool::try_lock(&lock, &lock_path)? {
+                return Ok(());
+            }
+            continue;
+        };
+
+        let now = now_ms();
+        if item.due > now {
+            tokio::time::sleep(Duration::from_millis(item.due - now).min(POLL)).await;
+            continue;
+        }
+        deliver(&item).await;
+    }
+}
+
+/// Delivers one due item and removes it. Failures are logged; the request is
+/// spooled when it asked for that, and otherwise retried with growing delays
+/// until `MAX_ATTEMPTS`, then moved aside.
+async fn deliver(item: &Item) {
+    let mut entry = match read_entry(&item.path) {
+        Ok(entry) => entry,
+        // Canceled after it was listed.
+        Err(_) if !item.path.exists() => return,
+        Err(error) => {
+            eprintln!("warning: {error:#}; moved aside");
+            spool::move_aside(&item.path);
+            return;
+        }
+    };
+    entry.request.print_id = false;
+    entry.request.await_result = false;
+
+    let Err(error) = backend::dispatch(&entry.request, &Connections::default()).await else {
+        if let Err(error) = remove_item(item) {
+            eprintln!("warning: {error:#}");
+        }
+        return;
+    };
+    if entry.request.spool {
+        match spool::write(&entry.request) {
+            Ok(path) => eprintln!("warning: {}: {error:#}; spooled to {}", item.id, path.display()),
+            Err(spool_error) => eprintln!("warning: {}: {error:#}; {spool_error:#}", item.id),
+        }
+        if let Err(error) = remove_item(item) {
+            eprintln!("warning: {error:#}");
+        }
+        return;
+    }
+
+    entry.attempts += 1;
+    if entry.attempts >= MAX_ATTEMPTS {
+        eprintln!("warning: {}: {error:#}; gave up after {} attempts, moved aside", item.id, entry.attempts);
+        spool::move_aside(&item.path);
+        return;
+    }
+    let delay = RETRY_DELAY
+        .saturating_mul(1 << (entry.attempts - 1).min(16))
+        .min(MAX_RETRY_DELAY);
+    let due = now_ms().saturating_add(delay.as_millis() as u64);
+    eprintln!("warning: {}: {error:#}; retrying at {}", item.id, format_time(due));
+    if let Err(error) = reschedule(item, due, &entry) {
+        eprintln!("warning: {error:#}");
+        spool::move_aside(&item.path);
+    }
+}
+
+/// Moves the item to its new due time under the same id.
+fn reschedule(item: &Item, due: u64, entry: &ScheduledEntry<Request>) -> Result<()> {
+    let dir = schedule_dir()?;
+    let entry = ScheduledEntry {
+        version: entry.version,
+        created: entry.created,
+        attempts: entry.attempts,
+        request: &entry.request,
+    };
+    write_entry(&dir, due, &item.id, &entry)?;
+    remove_item(item)
+}
+
+fn list_items() -> Result<Vec<Item>> {
+    let dir = schedule_dir()?;
+    let read_dir = match std::fs::read_dir(&dir) {
+        Ok(read_dir) => read_dir,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => {
+            return Err(error)
+                .with_context(|| format!("failed to read schedule directory: {}", dir.display()));
+        }
+    };
+
+    let mut items = Vec::new();
+    for dir_entry in read_dir {
+        let path = dir_entry
+            .with_context(|| format!("failed to read schedule directory: {}", dir.display()))?
+            .path();
+        let parsed = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .filter(|name| !name.starts_with('.'))
+            .and_then(|name| name.strip_suffix(".json"))
+            .and_then(|stem| stem.split_once('-'))
+            .and_then(|(due, id)| Some((due.parse::<u64>().ok()?, id.to_string())));
+        if let Some((due, id)) = parsed {
+            items.push(Item { id, due, path });
+        }
+    }
+    items.sort_by(|first, second| first.path.cmp(&second.path));
+    Ok(items)
+}
+
+fn read_entry(path: &Path) -> Result<ScheduledEntry<Request>> {
+    let contents = std::fs::read(path)
+        .with_context(|| format!("failed to read scheduled notification: {}", path.display()))?;
+    let entry: ScheduledEntry<Request> = serde_json::from_slice(&contents)
+        .with_context(|| format!("failed to parse scheduled notification: {}", path.display()))?;
+    if entry.version != SCHEDULE_VERSION {
+        bail!(
+            "unsupported scheduled notification version {} in {}",
+            entry.version,
+            path.display()
+        );
+    }
+    Ok(entry)
+}
+
+fn remove_item(item: &Item) -> Result<()> {
+    std::fs::remove_file(&item.path)
+        .with_context(|| format!("failed to remove scheduled notification: {}", item.path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unix_and_rfc3339_times() {
+        assert_eq!(parse_time("@4102444800").unwrap(), 4_102_444_800_000);
+        assert_eq!(parse_time(" 2100-01-01T00:00:00Z ").unwrap(), 4_102_444_800_000);
+        assert_eq!(parse_time("2100-01-01T01:00:00+01:00").unwrap(), 4_102_444_800_000);
+    }
+
+    #[test]
+    fn local_date_and_time() {
+        let expected = DateTime::constant(2100, 1, 1, 9, 30, 0, 0)
+            .to_zoned(TimeZone::system())
+            .unwrap()
+            .timestamp()
+            .as_millisecond() as u64;
+        assert_eq!(parse_time("2100-01-01 09:30").unwrap(), expected);
+        assert_eq!(parse_time("2100-01-01T09:30:00").unwrap(), expected);
+    }
+
+    #[test]
+    fn time_of_day_is_the_next_occurrence() {
+        let now = now_ms();
+        for text in ["00:00", "12:00", "23:59:59"] {
+            let due = parse_time(text).unwrap();
+            assert!(due > now, "{text}");
+            // A day, plus an hour for a DST change.
+            assert!(due - now <= 25 * 60 * 60 * 1000, "{text}");
+        }
+    }
+
+    #[test]
+    fn past_and_malformed_times_fail() {
+        for text in ["@0", "2000-01-01 00:00", "1999-12-31T23:00:00Z"] {
+            let error = parse_time(text).expect_err(text);
+            assert!(error.to_string().contains("is in the past"), "{text}: {error}");
+            assert!(check_time(text).is_ok(), "{text}");
+        }
+        for text in ["", "tomorrow", "25:00", "2100-13-01", "@soon", "@99999999999999999999"] {
+            let error = parse_time(text).expect_err(text);
+            assert!(error.to_string().starts_with("invalid"), "{text}: {error}");
+            assert!(check_time(text).is_err(), "{text}");
+        }
+    }
+}
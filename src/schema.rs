@@ -0,0 +1,30 @@
+//! `notify schema`: JSON Schema documents generated from the types that parse
+//! payloads and print events, so they cannot drift from the parser.
+
+use anyhow::{Context, Result};
+use clap::ValueEnum;
+use schemars::schema_for;
+
+use crate::backend::EventLine;
+use crate::{CardEnvelope, YamlPayload};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub(crate) enum SchemaKind {
+    /// the payload accepted on stdin, by --file and in templates
+    Payload,
+    /// the card JSON sent as the notification body
+    Card,
+    /// the JSON lines printed by --await, batches and flush
+    Events,
+}
+
+pub(crate) fn print(kind: SchemaKind) -> Result<()> {
+    let schema = match kind {
+        SchemaKind::Payload => schema_for!(YamlPayload),
+        SchemaKind::Card => schema_for!(CardEnvelope),
+        SchemaKind::Events => schema_for!(EventLine),
+    };
+    let output = serde_json::to_string_pretty(&schema).context("failed to render schema")?;
+    println!("{output}");
+    Ok(())
+}
@@ -0,0 +1,315 @@
+//! Card signing, so a daemon can tell our prompts from look-alikes sent by
+//! other processes on the bus. The signature is an HMAC-SHA256 over the card
+//! JSON (keys sorted, without `signature`), a nonce and an expiry, keyed by a
+//! local keyfile. Action ids of a signed card carry the nonce (`allow@NONCE`)
+//! and `--await` ignores actions that do not.
+
+use std::collections::BTreeMap;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, anyhow, bail};
+use hmac::{Hmac, Mac};
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize, Serializer};
+use sha2::{Digest, Sha256};
+
+use crate::{config_dir, hex};
+
+const ALGORITHM: &str = "hmac-sha256";
+const MIN_KEY_BYTES: usize = 16;
+
+/// Proof that a card came from the holder of the signing key.
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct CardSignature {
+    /// always "hmac-sha256"
+    pub(crate) alg: String,
+    /// first 8 bytes of the SHA-256 of the key, hex encoded, to pick the key
+    pub(crate) key_id: String,
+    /// random hex string; every action id of the card ends with "@" and this nonce
+    pub(crate) nonce: String,
+    /// unix time in seconds after which the card must not be trusted
+    pub(crate) expires: u64,
+    /// hex HMAC-SHA256 over "CARD\nNONCE\nEXPIRES", where CARD is the card JSON
+    /// without "signature", keys sorted, no whitespace
+    pub(crate) value: String,
+}
+
+pub(crate) struct SigningKey {
+    bytes: Vec<u8>,
+}
+
+fn default_key_path() -> Result<PathBuf> {
+    Ok(config_dir()?.join("notify").join("card.key"))
+}
+
+impl SigningKey {
+    /// Reads the keyfile, which must hold at least 16 bytes and must not be
+    /// accessible by other users.
+    pub(crate) fn load(path: Option<&Path>) -> Result<Self> {
+        let path = match path {
+            Some(path) => path.to_path_buf(),
+            None => default_key_path()?,
+        };
+        let bytes = std::fs::read(&path).with_context(|| {
+            format!(
+                "failed to read signing key: {0} (create one with: head -c 32 /dev/urandom > {0} && chmod 600 {0})",
+                path.display()
+            )
+        })?;
+        if bytes.len() < MIN_KEY_BYTES {
+            bail!("signing key {} is shorter than {MIN_KEY_BYTES} bytes", path.display());
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path)
+                .with_context(|| format!("failed to inspect signing key: {}", path.display()))?
+                .permissions()
+                .mode();
+            if mode & 0o077 != 0 {
+                bail!(
+                    "signing key {} is accessible by other users; run chmod 600 on it",
+                    path.display()
+                );
+            }
+        }
+        Ok(Self { bytes })
+    }
+
+    fn id(&self) -> String {
+        hex::encode(&Sha256::digest(&self.bytes)[..8])
+    }
+
+    fn mac(&self, card: &serde_json::Value, nonce: &str, expires: u64) -> Hmac<Sha256> {
+        let card = serde_json::to_string(&Canonical(card)).expect("JSON values always serialize");
+        let mut mac = Hmac::<Sha256>::new_from_slice(&self.bytes).expect("HMAC accepts any key length");
+        mac.update(format!("{card}\n{nonce}\n{expires}").as_bytes());
+        mac
+    }
+
+    /// Adds a `signature` to the card JSON and returns the signed JSON and its nonce.
+    pub(crate) fn sign(&self, card_json: &str, ttl_secs: u64) -> Result<(String, String)> {
+        let mut card = serde_json::from_str::<serde_json::Value>(card_json)
+            .context("failed to read card body for signing")?;
+        let mut nonce = [0_u8; 16];
+        getrandom::fill(&mut nonce).map_err(|error| anyhow!("failed to generate nonce: {error}"))?;
+        let nonce = hex::encode(&nonce);
+        let expires = now().saturating_add(ttl_secs);
+
+        let value = hex::encode(&self.mac(&card, &nonce, expires).finalize().into_bytes());
+        let signature = CardSignature {
+            alg: ALGORITHM.to_string(),
+            key_id: self.id(),
+            nonce: nonce.clone(),
+            expires,
+            value,
+        };
+        card.as_object_mut()
+            .ok_or_else(|| anyhow!("card body must be a JSON object"))?
+            .insert(
+                "signature".to_string(),
+                serde_json::to_value(signature).context("failed to encode card signature")?,
+            );
+        Ok((card.to_string(), nonce))
+    }
+
+    pub(crate) fn verify(&self, card_json: &str) -> Result<CardSignature> {
+        let mut card = serde_json::from_str::<serde_json::Value>(card_json)
+            .context("failed to parse card JSON")?;
+        let signature = card
+            .as_object_mut()
+            .ok_or_else(|| anyhow!("card must be a JSON object"))?
+            .remove("signature")
+            .ok_or_else(|| anyhow!("card is not signed"))?;
+        let signature = serde_json::from_value::<CardSignature>(signature)
+            .context("invalid card signature")?;
+
+        if signature.alg != ALGORITHM {
+            bail!("unsupported signature algorithm '{}'", signature.alg);
+        }
+        if signature.key_id != self.id() {
+            bail!(
+                "card was signed with another key (key id {}, this key is {})",
+                signature.key_id,
+                self.id()
+            );
+        }
+        let value = hex::decode(&signature.value).ok_or_else(|| anyhow!("invalid signature value"))?;
+        self.mac(&card, &signature.nonce, signature.expires)
+            .verify_slice(&value)
+            .map_err(|_| anyhow!("signature does not match the card"))?;
+        if signature.expires < now() {
+            bail!("signature expired at {} (unix time)", signature.expires);
+        }
+        Ok(signature)
+    }
+}
+
+/// JSON with the keys of every object sorted, whatever order `serde_json`
+/// keeps them in (`preserve_order` keeps insertion order).
+struct Canonical<'a>(&'a serde_json::Value);
+
+impl Serialize for Canonical<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.0 {
+            serde_json::Value::Object(object) => object
+                .iter()
+                .map(|(key, value)| (key, Canonical(value)))
+                .collect::<BTreeMap<_, _>>()
+                .serialize(serializer),
+            serde_json::Value::Array(items) => serializer.collect_seq(items.iter().map(Canonical)),
+            value => value.serialize(serializer),
+        }
+    }
+}
+
+/// The action id as sent to the server for a signed card.
+pub(crate) fn with_nonce(action_id: &str, nonce: &str) -> String {
+    format!("{action_id}@{nonce}")
+}
+
+/// The action key without our nonce, or `None` if it does not carry it.
+pub(crate) fn strip_nonce<'a>(action_key: &'a str, nonce: &str) -> Option<&'a str> {
+    action_key
+        .rsplit_once('@')
+        .filter(|(_, suffix)| *suffix == nonce)
+        .map(|(key, _)| key)
+}
+
+/// `notify verify`: checks the signature of a card body read from `path` or stdin.
+pub(crate) fn run_verify(path: Option<&Path>, key: Option<&Path>) -> Result<()> {
+    let mut input = String::new();
+    match path {
+        Some(path) if path.as_os_str() != "-" => {
+            input = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read card: {}", path.display()))?;
+        }
+        _ => {
+            io::stdin()
+                .read_to_string(&mut input)
+                .context("failed to read card from stdin")?;
+        }
+    }
+
+    let signature = SigningKey::load(key)?.verify(input.trim())?;
+    println!(
+        "verified: key {}, nonce {}, expires in {}s",
+        signature.key_id,
+        signature.nonce,
+        signature.expires.saturating_sub(now())
+    );
+    Ok(())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{CardChoice, CardEnvelope, CardPayload};
+
+    fn key(byte: u8) -> SigningKey {
+        SigningKey {
+            bytes: vec![byte; 32],
+        }
+    }
+
+    fn error(result: Result<CardSignature>) -> String {
+        format!("{:#}", result.expect_err("verification should fail"))
+    }
+
+    fn envelope(signature: Option<CardSignature>) -> CardEnvelope {
+        CardEnvelope {
+            xnotid_card: "v1".to_string(),
+            payload: CardPayload::MultipleChoice {
+                question: "Deploy?".to_string(),
+                choices: ["staging", "production"]
+                    .map(|id| CardChoice {
+                        id: id.to_string(),
+                        label: id.to_uppercase(),
+                    })
+                    .into(),
+                allow_other: false,
+            },
+            signature,
+        }
+    }
+
+    fn card() -> String {
+        serde_json::to_string(&envelope(None)).unwrap()
+    }
+
+    #[test]
+    fn canonical_json_sorts_keys_at_every_level() {
+        let value = serde_json::json!({"b": 1, "a": {"d": [{"z": 1, "y": "\"q"}], "c": null}});
+        assert_eq!(
+            serde_json::to_string(&Canonical(&value)).unwrap(),
+            r#"{"a":{"c":null,"d":[{"y":"\"q","z":1}]},"b":1}"#
+        );
+    }
+
+    #[test]
+    fn signed_card_verifies() {
+        let (signed, nonce) = key(1).sign(&card(), 60).unwrap();
+        let signature = key(1).verify(&signed).unwrap();
+        assert_eq!(signature.nonce, nonce);
+        assert_eq!(signature.alg, ALGORITHM);
+        assert!(signature.expires >= now() + 59);
+
+        // Key order and whitespace do not matter.
+        let mut reordered = serde_json::from_str::<serde_json::Value>(&signed).unwrap();
+        let object = reordered.as_object_mut().unwrap();
+        let question = object.remove("question").unwrap();
+        object.insert("question".to_string(), question);
+        key(1).verify(&serde_json::to_string_pretty(&reordered).unwrap()).unwrap();
+
+        // The daemon may read it back into the card type and serialize it again.
+        let envelope = serde_json::to_string(&envelope(Some(signature))).unwrap();
+        key(1).verify(&envelope).unwrap();
+    }
+
+    #[test]
+    fn tampered_or_foreign_cards_fail() {
+        let (signed, _) = key(1).sign(&card(), 60).unwrap();
+        assert!(error(key(2).verify(&signed)).contains("another key"));
+        for (from, to) in [("Deploy?", "Delete?"), ("PRODUCTION", "NOTHING"), ("false", "true")] {
+            let tampered = signed.replace(from, to);
+            assert!(error(key(1).verify(&tampered)).contains("does not match"), "{to}");
+        }
+        assert!(error(key(1).verify(&card())).contains("not signed"));
+    }
+
+    #[test]
+    fn expired_signature_fails() {
+        let key = key(1);
+        let card = serde_json::from_str::<serde_json::Value>(&card()).unwrap();
+        let expires = now() - 10;
+        let signature = CardSignature {
+            alg: ALGORITHM.to_string(),
+            key_id: key.id(),
+            nonce: "00".to_string(),
+            expires,
+            value: hex::encode(&key.mac(&card, "00", expires).finalize().into_bytes()),
+        };
+        let mut signed = card;
+        signed["signature"] = serde_json::to_value(signature).unwrap();
+        assert!(error(key.verify(&signed.to_string())).contains("expired"));
+    }
+
+    #[test]
+    fn nonce_is_stripped_only_when_it_matches() {
+        assert_eq!(strip_nonce(&with_nonce("allow", "ab12"), "ab12"), Some("allow"));
+        assert_eq!(strip_nonce("user@host@ab12", "ab12"), Some("user@host"));
+        assert_eq!(strip_nonce("allow@ffff", "ab12"), None);
+        assert_eq!(strip_nonce("allow", "ab12"), None);
+        assert_eq!(strip_nonce("allow@ab12x", "ab12"), None);
+    }
+}
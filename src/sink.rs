@@ -0,0 +1,76 @@
+//! Delivery by appending one JSON line per notification to a file.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, bail};
+use serde_json::json;
+
+use crate::backend::Delivery;
+use crate::{Request, generate_notification_id, state_dir};
+
+pub(crate) fn default_sink_path() -> Result<PathBuf> {
+    Ok(state_dir()?.join("notify").join("notifications.jsonl"))
+}
+
+pub(crate) fn deliver(request: &Request) -> Result<Delivery> {
+    if request.await_result {
+        bail!("--await is not supported by the file backend");
+    }
+
+    let path = match &request.sink_file {
+        Some(path) => path.clone(),
+        None => default_sink_path()?,
+    };
+    if let Some(parent) = path.parent()
+        && !parent.as_os_str().is_empty()
+    {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("failed to create directory: {}", parent.display()))?;
+    }
+
+    let notification_id = if request.replaces_id != 0 {
+        request.replaces_id
+    } else {
+        generate_notification_id()
+    };
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+
+    let actions = request
+        .actions
+        .chunks(2)
+        .filter_map(|pair| match pair {
+            [id, label] => Some(json!({"id": id, "label": label})),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let record = json!({
+        "id": notification_id,
+        "time": timestamp,
+        "app_name": request.app_name,
+        "summary": request.summary,
+        "body": request.render_body(false),
+        "icon": request.icon,
+        "urgency": request.urgency.as_hint_value(),
+        "actions": actions,
+        "expire_timeout": request.expire_timeout,
+    });
+
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .with_context(|| format!("failed to open sink file: {}", path.display()))?;
+    writeln!(file, "{record}")
+        .with_context(|| format!("failed to write sink file: {}", path.display()))?;
+
+    Ok(Delivery {
+        id: notification_id,
+        pending: None,
+    })
+}
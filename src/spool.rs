@@ -0,0 +1,325 @@
+//! Offline spool: requests that no backend could deliver are written to disk
+//! and redelivered in order by `notify flush`.
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result, anyhow, bail};
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use zbus::fdo::NameOwnerChangedStream;
+use zvariant::OwnedValue;
+
+use crate::backend::{self, Event, EventLine};
+use crate::dbus::Connections;
+use crate::{Bus, Request, hex, state_dir};
+
+const SPOOL_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SpoolEntry<R> {
+    version: u32,
+    created: u64,
+    expires: Option<u64>,
+    request: R,
+}
+
+pub(crate) fn spool_dir() -> Result<PathBuf> {
+    Ok(state_dir()?.join("notify").join("spool"))
+}
+
+/// Writes the fully merged request to the spool directory and returns its path.
+pub(crate) fn write(request: &Request) -> Result<PathBuf> {
+    let dir = spool_dir()?;
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("failed to create spool directory: {}", dir.display()))?;
+
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .context("system clock is before the Unix epoch")?;
+    let created = now.as_secs();
+    let entry = SpoolEntry {
+        version: SPOOL_VERSION,
+        created,
+        expires: request.spool_ttl.map(|ttl| created.saturating_add(ttl)),
+        request,
+    };
+
+    // Zero-padded nanoseconds keep lexical order equal to spool order.
+    let name = format!("{:020}-{}.json", now.as_nanos(), std::process::id());
+    let temp_path = dir.join(format!(".{name}.tmp"));
+    let path = dir.join(name);
+    let contents = serde_json::to_vec_pretty(&entry).context("failed to serialize spool entry")?;
+    std::fs::write(&temp_path, contents)
+        .with_context(|| format!("failed to write spool entry: {}", temp_path.display()))?;
+    std::fs::rename(&temp_path, &path)
+        .with_context(|| format!("failed to write spool entry: {}", path.display()))?;
+    Ok(path)
+}
+
+/// Redelivers spooled requests oldest first. Stops at the first failure so
+/// later items are never shown before earlier ones. With `watch`, waits for
+/// the blocking request's notification service to (re)appear on that
+/// request's bus and retries until the spool is empty. Only one flush runs at
+/// a time, so no item is delivered twice.
+pub(crate) async fn flush(watch: bool) -> Result<()> {
+    let dir = spool_dir()?;
+    std::fs::create_dir_all(&dir)
+        .with_context(|| format!("failed to create spool directory: {}", dir.display()))?;
+    let lock_path = dir.join(".lock");
+    let lock = open_lock(&lock_path)?;
+    if !try_lock(&lock, &lock_path)? {
+        bail!("another 'notify flush' is running");
+    }
+
+    if !watch {
+        if let Some(blocked) = flush_once().await? {
+            bail!("{} spooled notification(s) could not be delivered", blocked.remaining);
+        }
+        return Ok(());
+    }
+
+    // One subscription per service and bus seen blocking the spool; the
+    // connections are shared per bus.
+    let connections = Connections::default();
+    let mut watches: Vec<((Bus, String), NameOwnerChangedStream<'static>)> = Vec::new();
+    loop {
+        let Some(blocked) = flush_once().await? else {
+            return Ok(());
+        };
+
+        let service = (blocked.bus, blocked.dest);
+        let Some((_, owner_changes)) = watches.iter_mut().find(|(watched, _)| *watched == service) else {
+            let connection = connections.get(&service.0).await?;
+            let dbus = zbus::fdo::DBusProxy::new(&connection)
+                .await
+                .context("failed to create D-Bus proxy")?;
+            let owner_changes = dbus
+                .receive_name_owner_changed_with_args(&[(0, service.1.as_str())])
+                .await
+                .context("failed to subscribe to NameOwnerChanged")?;
+            watches.push((service, owner_changes));
+            // The service may have appeared before the subscription; retry.
+            continue;
+        };
+
+        loop {
+            let signal = owner_changes
+                .next()
+                .await
+                .ok_or_else(|| anyhow!("NameOwnerChanged signal stream ended"))?;
+            let args = signal.args().context("failed to decode NameOwnerChanged")?;
+            if args.new_owner().is_some() {
+                break;
+            }
+        }
+    }
+}
+
+/// The first spooled request that could not be delivered.
+struct Blocked {
+    /// Entries left in the spool, this one included.
+    remaining: usize,
+    bus: Bus,
+    dest: String,
+}
+
+async fn flush_once() -> Result<Option<Blocked>> {
+    let entries = list_entries()?;
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or_default();
+    let connections = Connections::default();
+
+    for (index, path) in entries.iter().enumerate() {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        // A truncated write or another version's entry must not block the rest.
+        let entry = match read_entry(path) {
+            Ok(entry) => entry,
+            Err(error) => {
+                eprintln!("warning: {error:#}; moved aside");
+                move_aside(path);
+                continue;
+            }
+        };
+
+        if entry.expires.is_some_and(|expires| expires <= now) {
+            remove_entry(path)?;
+            EventLine::new(Event::Expired { spool: name }).print();
+            continue;
+        }
+
+        let mut request = entry.request;
+        request.print_id = false;
+        request.await_result = false;
+        request.spool = false;
+
+        match backend::dispatch(&request, &connections).await {
+            Ok((backend, delivery)) => {
+                remove_entry(path)?;
+                let mut line = EventLine::new(Event::Delivered { spool: name });
+                line.id = Some(delivery.id);
+                line.backend = Some(backend);
+                line.print();
+            }
+            Err(error) => {
+                eprintln!("warning: failed to redeliver {name}: {error:#}");
+                return Ok(Some(Blocked {
+                    remaining: entries.len() - index,
+                    bus: request.bus,
+                    dest: request.dest,
+                }));
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+pub(crate) fn open_lock(path: &Path) -> Result<File> {
+    OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path)
+        .with_context(|| format!("failed to open lock file: {}", path.display()))
+}
+
+/// Takes the lock without waiting; `false` if another process holds it.
+pub(crate) fn try_lock(lock: &File, path: &Path) -> Result<bool> {
+    match lock.try_lock() {
+        Ok(()) => Ok(true),
+        Err(TryLockError::WouldBlock) => Ok(false),
+        Err(TryLockError::Error(error)) => {
+            Err(error).with_context(|| format!("failed to lock {}", path.display()))
+        }
+    }
+}
+
+/// Renames an unusable entry to `*.bad`, where it is kept for inspection.
+pub(crate) fn move_aside(path: &Path) {
+    let mut bad = path.to_path_buf().into_os_string();
+    bad.push(".bad");
+    if let Err(error) = std::fs::rename(path, &bad) {
+        eprintln!("warning: failed to move {}: {error}", path.display());
+    }
+}
+
+fn list_entries() -> Result<Vec<PathBuf>> {
+    let dir = spool_dir()?;
+    let read_dir = match std::fs::read_dir(&dir) {
+        Ok(read_dir) => read_dir,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => {
+            return Err(error)
+                .with_context(|| format!("failed to read spool directory: {}", dir.display()));
+        }
+    };
+
+    let mut entries = Vec::new();
+    for dir_entry in read_dir {
+        let path = dir_entry
+            .with_context(|| format!("failed to read spool directory: {}", dir.display()))?
+            .path();
+        let is_entry = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| !name.starts_with('.') && name.ends_with(".json"));
+        if is_entry {
+            entries.push(path);
+        }
+    }
+    entries.sort();
+    Ok(entries)
+}
+
+fn read_entry(path: &Path) -> Result<SpoolEntry<Request>> {
+    let contents = std::fs::read(path)
+        .with_context(|| format!("failed to read spool entry: {}", path.display()))?;
+    let entry: SpoolEntry<Request> = serde_json::from_slice(&contents)
+        .with_context(|| format!("failed to parse spool entry: {}", path.display()))?;
+    if entry.version != SPOOL_VERSION {
+        bail!(
+            "unsupported spool entry version {} in {}",
+            entry.version,
+            path.display()
+        );
+    }
+    Ok(entry)
+}
+
+fn remove_entry(path: &Path) -> Result<()> {
+    std::fs::remove_file(path)
+        .with_context(|| format!("failed to remove spool entry: {}", path.display()))
+}
+
+/// Hints are stored as their D-Bus `a{sv}` encoding (hex) so every variant type
+/// survives the round trip exactly.
+pub(crate) mod hints_codec {
+    use super::*;
+    use serde::{Deserializer, Serializer, de::Error as _, ser::Error as _};
+    use zvariant::{LE, serialized::Context as EncodingContext, serialized::Data};
+
+    pub(crate) fn serialize<S: Serializer>(
+        hints: &HashMap<String, OwnedValue>,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        let data = zvariant::to_bytes(EncodingContext::new_dbus(LE, 0), hints)
+            .map_err(S::Error::custom)?;
+        serializer.serialize_str(&hex::encode(&data))
+    }
+
+    pub(crate) fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<HashMap<String, OwnedValue>, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        let bytes = hex::decode(&encoded).ok_or_else(|| D::Error::custom("hints are not valid hex"))?;
+        let data = Data::new(bytes, EncodingContext::new_dbus(LE, 0));
+        let (hints, _) = data.deserialize().map_err(D::Error::custom)?;
+        Ok(hints)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[derive(Serialize, Deserialize)]
+        struct Hints {
+            #[serde(with = "super")]
+            hints: HashMap<String, OwnedValue>,
+        }
+
+        fn sample() -> HashMap<String, OwnedValue> {
+            let mut hints = HashMap::new();
+            hints.insert("urgency".to_string(), OwnedValue::from(2_u8));
+            hints.insert("transient".to_string(), OwnedValue::from(true));
+            hints.insert("x".to_string(), OwnedValue::from(-5_i32));
+            hints.insert(
+                "category".to_string(),
+                OwnedValue::try_from(zvariant::Value::from("email.arrived")).unwrap(),
+            );
+            hints
+        }
+
+        #[test]
+        fn round_trips_variant_types() {
+            let json = serde_json::to_string(&Hints { hints: sample() }).unwrap();
+            let decoded: Hints = serde_json::from_str(&json).unwrap();
+            assert_eq!(decoded.hints, sample());
+        }
+
+        #[test]
+        fn rejects_corrupt_encoding() {
+            for encoded in [r#"{"hints":"0"}"#, r#"{"hints":"zz"}"#, r#"{"hints":"0é0"}"#] {
+                let error = serde_json::from_str::<Hints>(encoded).err().unwrap();
+                assert!(error.to_string().contains("not valid hex"), "{error}");
+            }
+        }
+    }
+}
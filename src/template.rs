@@ -0,0 +1,313 @@
+//! Payload templates: YAML payloads whose text fields contain `{{name}}`
+//! placeholders, filled from `--var`, a vars file and the environment.
+//!
+//! Only template text is substituted; payloads read from stdin or `--file` are
+//! untrusted and never expanded.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde_yaml::{Mapping, Value};
+
+use crate::{config_dir, validate, yaml_tree};
+
+const TEXT_KEYS: [&str; 5] = ["summary", "body", "app_name", "icon", "category"];
+const CARD_TEXT_KEYS: [&str; 2] = ["question", "allow_label"];
+
+pub(crate) fn templates_dir() -> Result<PathBuf> {
+    Ok(config_dir()?.join("notify").join("templates"))
+}
+
+/// Resolves `name` to a file: anything that looks like a path is used as is,
+/// otherwise `<templates dir>/<name>.yaml` (or `.yml`).
+fn resolve(name: &str) -> Result<PathBuf> {
+    let path = Path::new(name);
+    if name.contains('/') || path.extension().is_some() {
+        return Ok(path.to_path_buf());
+    }
+
+    let dir = templates_dir()?;
+    for extension in ["yaml", "yml"] {
+        let candidate = dir.join(format!("{name}.{extension}"));
+        if candidate.is_file() {
+            return Ok(candidate);
+        }
+    }
+    bail!("template '{name}' not found in {}", dir.display())
+}
+
+pub(crate) fn load(name: &str) -> Result<Mapping> {
+    let path = resolve(name)?;
+    let input = std::fs::read_to_string(&path)
+        .with_context(|| format!("failed to read template: {}", path.display()))?;
+    let context = || format!("failed to parse template: {}", path.display());
+    let mut problems = Vec::new();
+    for document in yaml_tree::parse_documents(&input).with_context(context)? {
+        if !document.is_null() {
+            validate::check_payload(&document, &mut problems);
+        }
+    }
+    validate::ensure_valid(&problems, format_args!("template {}", path.display()))?;
+    match serde_yaml::from_str(&input).with_context(context)? {
+        Value::Mapping(mapping) => Ok(mapping),
+        Value::Null => Ok(Mapping::new()),
+        _ => bail!("template {} must be a mapping", path.display()),
+    }
+}
+
+/// Placeholder values; `--var` wins over the vars file, which wins over the environment.
+pub(crate) struct Vars {
+    values: HashMap<String, String>,
+}
+
+impl Vars {
+    pub(crate) fn collect(cli_vars: &[String], vars_file: Option<&Path>) -> Result<Self> {
+        let mut values = HashMap::new();
+
+        if let Some(path) = vars_file {
+            let input = std::fs::read_to_string(path)
+                .with_context(|| format!("failed to read vars file: {}", path.display()))?;
+            // YAML is a superset of JSON, so one parser covers both formats.
+            let parsed: Value = serde_yaml::from_str(&input)
+                .with_context(|| format!("failed to parse vars file: {}", path.display()))?;
+            let Value::Mapping(mapping) = parsed else {
+                bail!("vars file {} must be a mapping", path.display());
+            };
+            for (key, value) in mapping {
+                let key = scalar_to_string(&key)
+                    .ok_or_else(|| anyhow!("vars file {}: keys must be scalars", path.display()))?;
+                let value = scalar_to_string(&value).ok_or_else(|| {
+                    anyhow!("vars file {}: value of '{key}' must be a scalar", path.display())
+                })?;
+                values.insert(key, value);
+            }
+        }
+
+        for raw in cli_vars {
+            let (key, value) = raw
+                .split_once('=')
+                .ok_or_else(|| anyhow!("invalid --var '{raw}', expected KEY=VALUE"))?;
+            let key = key.trim();
+            if key.is_empty() {
+                bail!("invalid --var '{raw}', key cannot be empty");
+            }
+            values.insert(key.to_string(), value.to_string());
+        }
+
+        Ok(Self { values })
+    }
+
+    fn get(&self, name: &str) -> Option<String> {
+        self.values
+            .get(name)
+            .cloned()
+            .or_else(|| std::env::var(name).ok())
+    }
+}
+
+fn scalar_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::String(value) => Some(value.clone()),
+        Value::Bool(value) => Some(value.to_string()),
+        Value::Number(value) => Some(value.to_string()),
+        Value::Null => Some(String::new()),
+        _ => None,
+    }
+}
+
+/// Substitutes placeholders in the template's text fields, actions and card
+/// question/choices; fails listing every placeholder that has no value.
+pub(crate) fn render(mut template: Mapping, vars: &Vars) -> Result<Mapping> {
+    let mut unresolved = BTreeSet::new();
+
+    for key in TEXT_KEYS {
+        if let Some(value) = template.get_mut(key) {
+            render_string(value, vars, &mut unresolved);
+        }
+    }
+
+    if let Some(Value::Sequence(actions)) = template.get_mut("actions") {
+        for action in actions {
+            render_labeled(action, vars, &mut unresolved);
+        }
+    }
+
+    if let Some(Value::Mapping(card)) = template.get_mut("card") {
+        for key in CARD_TEXT_KEYS {
+            if let Some(value) = card.get_mut(key) {
+                render_string(value, vars, &mut unresolved);
+            }
+        }
+        if let Some(Value::Sequence(choices)) = card.get_mut("choices") {
+            for choice in choices {
+                render_labeled(choice, vars, &mut unresolved);
+            }
+        }
+    }
+
+    if !unresolved.is_empty() {
+        let names = unresolved.into_iter().collect::<Vec<_>>();
+        bail!("unresolved template placeholders: {}", names.join(", "));
+    }
+    Ok(template)
+}
+
+/// Actions and card choices are either `"id:label"` strings or `{id, label}` mappings.
+fn render_labeled(value: &mut Value, vars: &Vars, unresolved: &mut BTreeSet<String>) {
+    match value {
+        Value::Mapping(mapping) => {
+            for key in ["id", "label"] {
+                if let Some(value) = mapping.get_mut(key) {
+                    render_string(value, vars, unresolved);
+                }
+            }
+        }
+        value => render_string(value, vars, unresolved),
+    }
+}
+
+fn render_string(value: &mut Value, vars: &Vars, unresolved: &mut BTreeSet<String>) {
+    if let Value::String(text) = value {
+        *text = substitute(text, vars, unresolved);
+    }
+}
+
+fn substitute(text: &str, vars: &Vars, unresolved: &mut BTreeSet<String>) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{") {
+        output.push_str(&rest[..start]);
+        let after_open = &rest[start + 2..];
+        let Some(end) = after_open.find("}}") else {
+            output.push_str(&rest[start..]);
+            return output;
+        };
+
+        let name = after_open[..end].trim();
+        if is_placeholder_name(name) {
+            match vars.get(name) {
+                Some(value) => output.push_str(&value),
+                None => {
+                    unresolved.insert(name.to_string());
+                }
+            }
+        } else {
+            output.push_str(&rest[start..start + 2 + end + 2]);
+        }
+        rest = &after_open[end + 2..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+fn is_placeholder_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|character| character.is_ascii_alphanumeric() || matches!(character, '_' | '-' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn vars(cli_vars: &[&str]) -> Vars {
+        let cli_vars = cli_vars.iter().map(ToString::to_string).collect::<Vec<_>>();
+        Vars::collect(&cli_vars, None).unwrap()
+    }
+
+    fn substituted(text: &str, vars: &Vars) -> (String, Vec<String>) {
+        let mut unresolved = BTreeSet::new();
+        let output = substitute(text, vars, &mut unresolved);
+        (output, unresolved.into_iter().collect())
+    }
+
+    fn mapping(yaml: &str) -> Mapping {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn placeholders_are_replaced() {
+        let vars = vars(&["host=web-1", "job.name=deploy", "empty=", "eq=a=b"]);
+        assert_eq!(substituted("{{host}}: {{ job.name }}", &vars), ("web-1: deploy".to_string(), vec![]));
+        assert_eq!(substituted("[{{empty}}] {{eq}}", &vars), ("[] a=b".to_string(), vec![]));
+        // Values are not expanded again.
+        let nested = Vars::collect(&["a={{b}}".to_string(), "b=x".to_string()], None).unwrap();
+        assert_eq!(substituted("{{a}}", &nested).0, "{{b}}");
+    }
+
+    #[test]
+    fn non_placeholders_are_kept() {
+        let vars = vars(&[]);
+        for text in ["{{}}", "{{ two words }}", "{{x", "a }} b", "{{{json}"] {
+            assert_eq!(substituted(text, &vars), (text.to_string(), vec![]), "{text}");
+        }
+    }
+
+    #[test]
+    fn unknown_placeholders_are_listed() {
+        let vars = vars(&["known=1"]);
+        let (_, unresolved) = substituted("{{zeta}} {{known}} {{alpha}} {{zeta}}", &vars);
+        assert_eq!(unresolved, ["alpha", "zeta"]);
+
+        let error = render(mapping("summary: '{{nope_1}}'\nbody: '{{nope_2}}'"), &vars).unwrap_err();
+        assert_eq!(error.to_string(), "unresolved template placeholders: nope_1, nope_2");
+    }
+
+    #[test]
+    fn precedence_is_var_then_vars_file_then_environment() {
+        let Ok(home) = std::env::var("HOME") else {
+            return;
+        };
+        let path = std::env::temp_dir().join(format!("notify-test-{}-vars.yaml", std::process::id()));
+        std::fs::write(&path, "HOME: from-file\nshared: file\ncount: 3\nflag: true\n").unwrap();
+        let collected = Vars::collect(&["shared=cli".to_string()], Some(&path));
+        let _ = std::fs::remove_file(&path);
+        let collected = collected.unwrap();
+
+        assert_eq!(collected.get("shared").as_deref(), Some("cli"));
+        assert_eq!(collected.get("HOME").as_deref(), Some("from-file"));
+        assert_eq!(collected.get("count").as_deref(), Some("3"));
+        assert_eq!(collected.get("flag").as_deref(), Some("true"));
+        assert_eq!(vars(&[]).get("HOME"), Some(home));
+        assert_eq!(vars(&["HOME=cli"]).get("HOME").as_deref(), Some("cli"));
+    }
+
+    #[test]
+    fn invalid_vars_fail() {
+        for (raw, message) in [("novalue", "expected KEY=VALUE"), (" =x", "key cannot be empty")] {
+            let error = Vars::collect(&[raw.to_string()], None).err().expect(raw);
+            assert!(error.to_string().contains(message), "{error}");
+        }
+        let path = std::env::temp_dir().join(format!("notify-test-{}-bad-vars.yaml", std::process::id()));
+        std::fs::write(&path, "list: [1, 2]\n").unwrap();
+        let error = Vars::collect(&[], Some(&path)).err();
+        let _ = std::fs::remove_file(&path);
+        assert!(error.unwrap().to_string().contains("value of 'list' must be a scalar"));
+    }
+
+    #[test]
+    fn only_text_fields_are_rendered() {
+        let template = mapping(
+            "summary: '{{who}}'\n\
+             icon: '{{who}}.png'\n\
+             dest: '{{who}}'\n\
+             actions: ['ok:{{who}}', {id: '{{who}}', label: Hi}]\n\
+             card: {type: multiple-choice, question: '{{who}}?', choices: ['{{who}}', {id: x, label: '{{who}}'}]}\n",
+        );
+        let rendered = render(template, &vars(&["who=ann"])).unwrap();
+        assert_eq!(
+            rendered,
+            mapping(
+                "summary: ann\n\
+                 icon: ann.png\n\
+                 dest: '{{who}}'\n\
+                 actions: ['ok:ann', {id: ann, label: Hi}]\n\
+                 card: {type: multiple-choice, question: ann?, choices: [ann, {id: x, label: ann}]}\n",
+            )
+        );
+    }
+}
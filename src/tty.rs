@@ -0,0 +1,138 @@
+//! Delivery as a plain-text prompt on the controlling tty.
+//!
+//! With `--await`, actions are listed as numbered choices and the user's answer
+//! is read back from the same tty.
+
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+
+use anyhow::{Context, Result, bail};
+
+use crate::backend::{Delivery, EventReporter, Pending as BackendPending};
+use crate::{Request, generate_notification_id};
+
+pub(crate) const TTY_PATH: &str = "/dev/tty";
+
+const DISMISSED_REASON: u32 = 2;
+
+pub(crate) fn deliver(request: &Request) -> Result<Delivery> {
+    let mut tty = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .open(TTY_PATH)
+        .context("failed to open controlling terminal")?;
+
+    let notification_id = if request.replaces_id != 0 {
+        request.replaces_id
+    } else {
+        generate_notification_id()
+    };
+
+    let actions = request
+        .actions
+        .chunks(2)
+        .filter_map(|pair| match pair {
+            [id, label] => Some((sanitize_tty_text(id), sanitize_tty_text(label))),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut output = String::from("\n");
+    let summary = sanitize_tty_text(&request.summary);
+    let app_name = sanitize_tty_text(&request.app_name);
+    output.push_str(&format!("[{app_name}] {summary}\n"));
+    let body = sanitize_tty_text(&request.render_body(false));
+    if !body.is_empty() {
+        output.push_str(&body);
+        output.push('\n');
+    }
+    if request.await_result {
+        for (index, (_, label)) in actions.iter().enumerate() {
+            output.push_str(&format!("  {}) {label}\n", index + 1));
+        }
+    }
+    tty.write_all(output.as_bytes())
+        .and_then(|_| tty.flush())
+        .context("failed to write terminal prompt")?;
+
+    let pending = request
+        .await_result
+        .then(|| BackendPending::Tty(Pending { tty, actions }));
+
+    Ok(Delivery {
+        id: notification_id,
+        pending,
+    })
+}
+
+pub(crate) struct Pending {
+    tty: File,
+    actions: Vec<(String, String)>,
+}
+
+impl Pending {
+    pub(crate) async fn wait(&mut self, events: &EventReporter) -> Result<()> {
+        let tty = self.tty.try_clone().context("failed to reopen controlling terminal")?;
+        let actions = self.actions.clone();
+        let answer = tokio::task::spawn_blocking(move || prompt(tty, &actions))
+            .await
+            .context("terminal prompt task failed")??;
+
+        match answer {
+            Some(action_key) => events.action(action_key),
+            None => events.closed(DISMISSED_REASON),
+        }
+        Ok(())
+    }
+}
+
+/// Reads answers until one names an action (by number or id); an empty line
+/// dismisses the notification.
+fn prompt(mut tty: File, actions: &[(String, String)]) -> Result<Option<String>> {
+    let question = if actions.is_empty() {
+        "press Enter to dismiss: ".to_string()
+    } else {
+        format!("select [1-{}], empty to dismiss: ", actions.len())
+    };
+
+    let mut reader = BufReader::new(tty.try_clone().context("failed to reopen controlling terminal")?);
+    loop {
+        tty.write_all(question.as_bytes())
+            .and_then(|_| tty.flush())
+            .context("failed to write terminal prompt")?;
+
+        let mut line = String::new();
+        if reader
+            .read_line(&mut line)
+            .context("failed to read terminal answer")?
+            == 0
+        {
+            bail!("terminal closed before an answer was given");
+        }
+
+        let answer = line.trim();
+        if answer.is_empty() {
+            return Ok(None);
+        }
+        let selected = answer
+            .parse::<usize>()
+            .ok()
+            .and_then(|number| number.checked_sub(1))
+            .and_then(|index| actions.get(index))
+            .or_else(|| actions.iter().find(|(id, _)| id == answer));
+        if let Some((id, _)) = selected {
+            return Ok(Some(id.clone()));
+        }
+        let answer = sanitize_tty_text(answer);
+        tty.write_all(format!("unknown choice '{answer}'\n").as_bytes())
+            .context("failed to write terminal prompt")?;
+    }
+}
+
+/// Strips control characters except newline and tab so text cannot drive the terminal.
+fn sanitize_tty_text(value: &str) -> String {
+    value
+        .chars()
+        .filter(|character| matches!(character, '\n' | '\t') || !character.is_control())
+        .collect()
+}
@@ -0,0 +1,576 @@
+//! Payload validation: unknown keys (with "did you mean" suggestions) and
+//! conflicting fields, reported with line and column when the payload came from
+//! YAML or JSON. Also backs `notify validate`.
+
+use std::fmt;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use anyhow::{Context, Result, bail};
+use clap::Parser;
+use schemars::{Schema, schema_for};
+use serde_yaml::Value;
+
+use crate::format::{self, PayloadFormat};
+use crate::redact::RedactConfig;
+use crate::sanitize::SanitizePolicy;
+use crate::yaml_tree::{self, Node, NodeKind, Position};
+use crate::{Cli, StdinInput, YamlPayload, hints, merge_request, resolve_relative_paths, schedule};
+
+/// Every key `YamlPayload` accepts, taken from its schema so the two cannot drift.
+static PAYLOAD_KEYS: LazyLock<Vec<String>> = LazyLock::new(|| schema_keys(schema_for!(YamlPayload)));
+static SANITIZE_KEYS: LazyLock<Vec<String>> =
+    LazyLock::new(|| schema_keys(schema_for!(SanitizePolicy)));
+static REDACT_KEYS: LazyLock<Vec<String>> = LazyLock::new(|| schema_keys(schema_for!(RedactConfig)));
+
+fn schema_keys(schema: Schema) -> Vec<String> {
+    schema
+        .get("properties")
+        .and_then(|properties| properties.as_object())
+        .map(|properties| properties.keys().cloned().collect())
+        .unwrap_or_default()
+}
+
+/// Pairs of payload keys that set the same thing.
+const CONFLICTS: [(&str, &str); 9] = [
+    ("id", "replace"),
+    ("timeout", "expire_time"),
+    ("card", "body"),
+    ("card", "body_file"),
+    ("card", "body_command"),
+    ("body", "body_file"),
+    ("body", "body_command"),
+    ("body_file", "body_command"),
+    ("body_head", "body_tail"),
+];
+
+const CARD_TYPES: [&str; 2] = ["multiple-choice", "permission"];
+const MULTIPLE_CHOICE_KEYS: [&str; 4] = ["type", "question", "choices", "allow_other"];
+const PERMISSION_KEYS: [&str; 3] = ["type", "question", "allow_label"];
+const LABELED_KEYS: [&str; 2] = ["id", "label"];
+const URGENCIES: [&str; 3] = ["low", "normal", "critical"];
+const CONFIG_KEYS: [&str; 2] = ["defaults", "profiles"];
+
+#[derive(Debug)]
+pub(crate) struct Problem {
+    pub(crate) position: Option<Position>,
+    pub(crate) message: String,
+}
+
+impl Problem {
+    pub(crate) fn at(node: &Node, message: impl Into<String>) -> Self {
+        Self {
+            position: node.position,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.position {
+            Some(position) => write!(formatter, "{position}: {}", self.message),
+            None => formatter.write_str(&self.message),
+        }
+    }
+}
+
+/// Fails listing every problem, if there are any.
+pub(crate) fn ensure_valid(problems: &[Problem], what: impl fmt::Display) -> Result<()> {
+    if problems.is_empty() {
+        return Ok(());
+    }
+    let mut problems = problems.iter().collect::<Vec<_>>();
+    problems.sort_by_key(|problem| problem.position);
+    let lines = problems.iter().map(ToString::to_string).collect::<Vec<_>>();
+    bail!("invalid {what}:\n  {}", lines.join("\n  "))
+}
+
+/// Trees for `payloads` with source positions where they can be recovered:
+/// from the YAML stream, or from the JSON text (one document or JSON Lines).
+/// TOML payloads, and anything that does not line up, get no positions.
+pub(crate) fn located_documents(input: &str, format: PayloadFormat, payloads: &[Value]) -> Vec<Node> {
+    let located = match format {
+        PayloadFormat::Yaml => yaml_tree::parse_documents(input).ok(),
+        PayloadFormat::Json if payloads.len() == 1 => yaml_tree::parse_documents(input).ok(),
+        PayloadFormat::Json => json_lines(input),
+        PayloadFormat::Toml => None,
+    }
+    .map(|documents| {
+        documents
+            .into_iter()
+            .filter(|document| !document.is_null())
+            .collect::<Vec<_>>()
+    })
+    .filter(|documents| documents.len() == payloads.len());
+
+    located.unwrap_or_else(|| payloads.iter().map(Node::from_value).collect())
+}
+
+fn json_lines(input: &str) -> Option<Vec<Node>> {
+    let mut documents = Vec::new();
+    for (index, line) in input.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        for mut document in yaml_tree::parse_documents(line).ok()? {
+            shift_lines(&mut document, index);
+            documents.push(document);
+        }
+    }
+    Some(documents)
+}
+
+fn shift_lines(node: &mut Node, offset: usize) {
+    if let Some(position) = &mut node.position {
+        position.line += offset;
+    }
+    match &mut node.kind {
+        NodeKind::Sequence(items) => items.iter_mut().for_each(|item| shift_lines(item, offset)),
+        NodeKind::Mapping(entries) => {
+            for (key, value) in entries {
+                shift_lines(key, offset);
+                shift_lines(value, offset);
+            }
+        }
+        NodeKind::Scalar { .. } | NodeKind::Alias => {}
+    }
+}
+
+/// Checks every document; without positions, problems in a batch name their document.
+pub(crate) fn check_documents(documents: &[Node]) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    for (index, document) in documents.iter().enumerate() {
+        let start = problems.len();
+        check_payload(document, &mut problems);
+        if documents.len() > 1 {
+            for problem in &mut problems[start..] {
+                if problem.position.is_none() {
+                    problem.message = format!("document {}: {}", index + 1, problem.message);
+                }
+            }
+        }
+    }
+    problems
+}
+
+pub(crate) fn check_payload(node: &Node, problems: &mut Vec<Problem>) {
+    let NodeKind::Mapping(entries) = &node.kind else {
+        problems.push(Problem::at(node, "payload must be a mapping"));
+        return;
+    };
+
+    let keys = PAYLOAD_KEYS.iter().map(String::as_str).collect::<Vec<_>>();
+    check_keys(entries, &keys, "field", problems);
+
+    for (first, second) in CONFLICTS {
+        if let (Some(_), Some(value)) = (node.get(first), node.get(second)) {
+            problems.push(Problem::at(
+                value,
+                format!("'{first}' and '{second}' conflict; set only one"),
+            ));
+        }
+    }
+    if let (Some(value), Some(system)) = (node.get("bus_address"), node.get("system"))
+        && system.as_str() == Some("true")
+    {
+        problems.push(Problem::at(
+            value,
+            "'bus_address' and 'system: true' conflict; set only one",
+        ));
+    }
+
+    if let (Some(value), Some(silent)) = (node.get("sound"), node.get("silent"))
+        && silent.as_str() == Some("true")
+    {
+        problems.push(Problem::at(value, "'sound' and 'silent: true' conflict; set only one"));
+    }
+    if let Some(sounds) = node.get("sounds") {
+        match &sounds.kind {
+            NodeKind::Mapping(entries) => check_keys(entries, &URGENCIES, "urgency", problems),
+            _ => problems.push(Problem::at(sounds, "'sounds' must be a mapping of urgency to sound")),
+        }
+    }
+    if let Some(sanitize) = node.get("sanitize") {
+        match &sanitize.kind {
+            NodeKind::Mapping(entries) => {
+                let keys = SANITIZE_KEYS.iter().map(String::as_str).collect::<Vec<_>>();
+                check_keys(entries, &keys, "sanitize field", problems);
+            }
+            _ => problems.push(Problem::at(sanitize, "'sanitize' must be a mapping")),
+        }
+    }
+    if let Some(redact) = node.get("redact") {
+        match &redact.kind {
+            NodeKind::Mapping(entries) => {
+                let keys = REDACT_KEYS.iter().map(String::as_str).collect::<Vec<_>>();
+                check_keys(entries, &keys, "redact field", problems);
+            }
+            _ => problems.push(Problem::at(redact, "'redact' must be a mapping")),
+        }
+    }
+
+    if let Some(command) = node.get("body_command")
+        && matches!(&command.kind, NodeKind::Sequence(argv) if argv.is_empty())
+    {
+        problems.push(Problem::at(command, "'body_command' must name a program"));
+    }
+
+    if let Some(actions) = node.get("actions") {
+        check_labeled_list(actions, "action", problems);
+    }
+    if let Some(card) = node.get("card") {
+        check_card(card, problems);
+    }
+}
+
+fn check_card(card: &Node, problems: &mut Vec<Problem>) {
+    let NodeKind::Mapping(entries) = &card.kind else {
+        problems.push(Problem::at(card, "'card' must be a mapping"));
+        return;
+    };
+
+    let allowed: &[&str] = match card.get("type") {
+        None => {
+            problems.push(Problem::at(
+                card,
+                format!("card is missing 'type' ({})", CARD_TYPES.join(" or ")),
+            ));
+            return;
+        }
+        Some(kind) => match kind.as_str() {
+            Some("multiple-choice") => &MULTIPLE_CHOICE_KEYS,
+            Some("permission") => &PERMISSION_KEYS,
+            other => {
+                let other = other.unwrap_or_default();
+                let message = match suggest(other, &CARD_TYPES) {
+                    Some(suggestion) => {
+                        format!("unknown card type '{other}' (did you mean '{suggestion}'?)")
+                    }
+                    None => format!(
+                        "unknown card type '{other}' (expected {})",
+                        CARD_TYPES.join(" or ")
+                    ),
+                };
+                problems.push(Problem::at(kind, message));
+                return;
+            }
+        },
+    };
+
+    check_keys(entries, allowed, "card field", problems);
+    if let Some(choices) = card.get("choices") {
+        check_labeled_list(choices, "card choice", problems);
+    }
+}
+
+/// Actions and card choices are either strings or `{id, label}` mappings.
+fn check_labeled_list(node: &Node, what: &str, problems: &mut Vec<Problem>) {
+    let NodeKind::Sequence(items) = &node.kind else {
+        return;
+    };
+    for item in items {
+        if let NodeKind::Mapping(entries) = &item.kind {
+            check_keys(entries, &LABELED_KEYS, &format!("{what} field"), problems);
+        }
+    }
+}
+
+/// `config.yaml`: `defaults` and each profile are payload fragments.
+pub(crate) fn check_config(node: &Node) -> Vec<Problem> {
+    let mut problems = Vec::new();
+    let NodeKind::Mapping(entries) = &node.kind else {
+        if !node.is_null() {
+            problems.push(Problem::at(node, "config must be a mapping"));
+        }
+        return problems;
+    };
+
+    check_keys(entries, &CONFIG_KEYS, "config key", &mut problems);
+    if let Some(defaults) = node.get("defaults")
+        && !defaults.is_null()
+    {
+        check_payload(defaults, &mut problems);
+    }
+    if let Some(NodeKind::Mapping(profiles)) = node.get("profiles").map(|profiles| &profiles.kind) {
+        for (_, profile) in profiles {
+            check_payload(profile, &mut problems);
+        }
+    }
+    problems
+}
+
+fn check_keys(entries: &[(Node, Node)], allowed: &[&str], what: &str, problems: &mut Vec<Problem>) {
+    for (key, _) in entries {
+        let Some(name) = key.as_str() else {
+            problems.push(Problem::at(key, format!("{what} names must be strings")));
+            continue;
+        };
+        if allowed.contains(&name) {
+            continue;
+        }
+        let message = match suggest(name, allowed) {
+            Some(suggestion) => format!("unknown {what} '{name}' (did you mean '{suggestion}'?)"),
+            None => format!("unknown {what} '{name}'"),
+        };
+        problems.push(Problem::at(key, message));
+    }
+}
+
+/// The closest candidate within a third of the name's length in edits.
+fn suggest<'a>(name: &str, candidates: &[&'a str]) -> Option<&'a str> {
+    candidates
+        .iter()
+        .map(|candidate| (edit_distance(name, candidate), *candidate))
+        .filter(|(distance, candidate)| *distance <= (name.len().max(candidate.len()) / 3).max(1))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate)
+}
+
+/// Optimal string alignment distance: edits, counting a swap of two adjacent
+/// characters (`lable`) as one.
+fn edit_distance(left: &str, right: &str) -> usize {
+    let left = left.chars().collect::<Vec<_>>();
+    let right = right.chars().collect::<Vec<_>>();
+    let mut distances = vec![vec![0; right.len() + 1]; left.len() + 1];
+    for (row, distances) in distances.iter_mut().enumerate() {
+        distances[0] = row;
+    }
+    for (column, distance) in distances[0].iter_mut().enumerate() {
+        *distance = column;
+    }
+
+    for row in 1..=left.len() {
+        for column in 1..=right.len() {
+            let cost = usize::from(left[row - 1] != right[column - 1]);
+            let mut distance = (distances[row - 1][column] + 1)
+                .min(distances[row][column - 1] + 1)
+                .min(distances[row - 1][column - 1] + cost);
+            if row > 1
+                && column > 1
+                && left[row - 1] == right[column - 2]
+                && left[row - 2] == right[column - 1]
+            {
+                distance = distance.min(distances[row - 2][column - 2] + 1);
+            }
+            distances[row][column] = distance;
+        }
+    }
+    distances[left.len()][right.len()]
+}
+
+/// `notify validate`: lints payload files without sending anything. Prints
+/// `path:line:column: problem` lines (or `path: ok`) and fails if any file has
+/// problems. Files the payloads name but that do not exist are only warnings.
+pub(crate) fn run(files: &[PathBuf], format: Option<PayloadFormat>, strict_yaml: bool) -> Result<()> {
+    let mut total = 0;
+    for path in files {
+        let name = if path.as_os_str() == "-" {
+            "<stdin>".to_string()
+        } else {
+            path.display().to_string()
+        };
+        let mut warnings = Vec::new();
+        let mut problems = validate_file(path, format, strict_yaml, &mut warnings)?;
+        for warning in &warnings {
+            eprintln!("warning: {name}: {warning}");
+        }
+        problems.sort_by_key(|problem| problem.position);
+        if problems.is_empty() {
+            println!("{name}: ok");
+        }
+        for problem in &problems {
+            match problem.position {
+                Some(position) => println!("{name}:{position}: {}", problem.message),
+                None => println!("{name}: {}", problem.message),
+            }
+        }
+        total += problems.len();
+    }
+
+    if total > 0 {
+        bail!("{total} problem(s) found");
+    }
+    Ok(())
+}
+
+fn validate_file(
+    path: &Path,
+    format: Option<PayloadFormat>,
+    strict_yaml: bool,
+    warnings: &mut Vec<String>,
+) -> Result<Vec<Problem>> {
+    let mut input = String::new();
+    if path.as_os_str() == "-" {
+        io::stdin()
+            .read_to_string(&mut input)
+            .context("failed to read payload from stdin")?;
+    } else {
+        input = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read payload file: {}", path.display()))?;
+    }
+
+    let format = PayloadFormat::resolve(format, Some(path), &input);
+    let unlocated = |message: String| Problem {
+        position: None,
+        message,
+    };
+
+    let mut problems = Vec::new();
+    if strict_yaml && format == PayloadFormat::Yaml {
+        match format::strict_problems(&input) {
+            Ok(strict) => problems.extend(strict),
+            Err(error) => return Ok(vec![unlocated(format!("{error:#}"))]),
+        }
+    }
+    let mut payloads = match format::parse_payloads(&input, format, false) {
+        Ok(payloads) => payloads,
+        Err(error) => return Ok(vec![unlocated(format!("{error:#}"))]),
+    };
+
+    problems.extend(check_documents(&located_documents(&input, format, &payloads)));
+    if !problems.is_empty() {
+        return Ok(problems);
+    }
+
+    // Structurally fine; build each request to catch bad values too.
+    resolve_relative_paths(&mut payloads, path);
+    let cli = Cli::parse_from(["notify"]);
+    let count = payloads.len();
+    for (index, mut payload) in payloads.into_iter().enumerate() {
+        // Resolved by the config layering, which linting skips.
+        if let Value::Mapping(mapping) = &mut payload {
+            mapping.remove("profile");
+        }
+        let document = if count > 1 {
+            format!("document {}: ", index + 1)
+        } else {
+            String::new()
+        };
+        let request = serde_yaml::from_value::<YamlPayload>(payload)
+            .context("failed to parse payload")
+            .and_then(|mut payload| {
+                for (field, file) in detach_environment(&mut payload)? {
+                    if !file.exists() {
+                        warnings.push(format!("{document}{field} file not found: {}", file.display()));
+                    }
+                }
+                merge_request(cli.clone(), Some(payload), &StdinInput::default())
+            });
+        if let Err(error) = request {
+            problems.push(unlocated(format!("{document}{error:#}")));
+        }
+    }
+    Ok(problems)
+}
+
+/// Takes out what building the request would read or run, or resolve against
+/// the clock, so a payload lints the same anywhere and at any time. A time
+/// only has to parse; the files are returned to be looked up.
+fn detach_environment(payload: &mut YamlPayload) -> Result<Vec<(&'static str, PathBuf)>> {
+    payload.body_command = None;
+    if let Some(time) = payload.deliver_at.take() {
+        schedule::check_time(&time)?;
+    }
+
+    let sound_file = |sound: &str| PathBuf::from(hints::sound_path(sound).unwrap_or(sound));
+    let mut files = Vec::new();
+    files.extend(payload.body_file.take().map(|path| ("body_file", path)));
+    // Stdin is refused the same way everywhere.
+    files.extend(payload.image.take_if(|image| image.as_os_str() != "-").map(|path| ("image", path)));
+    if let Some(sound) = payload.sound.take_if(|sound| hints::sound_path(sound).is_some()) {
+        files.push(("sound", sound_file(&sound)));
+    }
+    if let Some(sounds) = &mut payload.sounds {
+        files.extend(sounds.take_files().iter().map(|sound| ("sounds", sound_file(sound))));
+    }
+    if let Some(hint) = payload.hints.remove("sound-file") {
+        let value = hints::from_yaml("sound-file", &hint)?;
+        let path = <&str>::try_from(&value).context("hint 'sound-file' must be a string")?;
+        files.push(("sound-file", PathBuf::from(path)));
+    }
+    if payload.sign.take().unwrap_or(false) {
+        files.extend(payload.signing_key.take().map(|path| ("signing_key", path)));
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn problems(input: &str) -> Vec<String> {
+        let documents = yaml_tree::parse_documents(input).unwrap();
+        check_documents(&documents).iter().map(ToString::to_string).collect()
+    }
+
+    #[test]
+    fn edit_distance_counts_swaps_once() {
+        assert_eq!(edit_distance("label", "label"), 0);
+        assert_eq!(edit_distance("lable", "label"), 1);
+        assert_eq!(edit_distance("sumary", "summary"), 1);
+        assert_eq!(edit_distance("urgncy", "urgency"), 1);
+        assert_eq!(edit_distance("", "body"), 4);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("größe", "grösse"), 2);
+    }
+
+    #[test]
+    fn suggestions_stay_close() {
+        let candidates = ["summary", "body", "urgency", "timeout", "icon"];
+        assert_eq!(suggest("sumary", &candidates), Some("summary"));
+        assert_eq!(suggest("timout", &candidates), Some("timeout"));
+        assert_eq!(suggest("bdoy", &candidates), Some("body"));
+        // One edit is always allowed, even for short names.
+        assert_eq!(suggest("ico", &candidates), Some("icon"));
+        assert_eq!(suggest("colour", &candidates), None);
+        assert_eq!(suggest("", &candidates), None);
+    }
+
+    #[test]
+    fn unknown_keys_are_located_with_suggestions() {
+        assert_eq!(
+            problems("summary: hi\nurgncy: low\ncolour: red\n"),
+            [
+                "2:1: unknown field 'urgncy' (did you mean 'urgency'?)",
+                "3:1: unknown field 'colour'"
+            ]
+        );
+        assert_eq!(
+            problems("card:\n  type: permision\n  question: ok?\n"),
+            ["2:9: unknown card type 'permision' (did you mean 'permission'?)"]
+        );
+        assert_eq!(
+            problems("actions:\n  - {id: a, lable: A}\nsanitize: {max_bdy: 5}\n"),
+            [
+                "3:12: unknown sanitize field 'max_bdy' (did you mean 'max_body'?)",
+                "2:13: unknown action field 'lable' (did you mean 'label'?)"
+            ]
+        );
+    }
+
+    #[test]
+    fn conflicts_and_shapes() {
+        assert_eq!(
+            problems("id: 1\nreplace: 2\nbody: x\nbody_file: y\n"),
+            [
+                "2:10: 'id' and 'replace' conflict; set only one",
+                "4:12: 'body' and 'body_file' conflict; set only one"
+            ]
+        );
+        assert_eq!(problems("sound: bell\nsilent: true\n"), ["1:8: 'sound' and 'silent: true' conflict; set only one"]);
+        assert!(problems("sound: bell\nsilent: false\n").is_empty());
+        assert_eq!(problems("- a\n"), ["1:1: payload must be a mapping"]);
+        assert_eq!(problems("body_command: []\n"), ["1:15: 'body_command' must name a program"]);
+    }
+
+    #[test]
+    fn batch_problems_name_their_document_without_a_position() {
+        let documents = ["colour: red", "summary: ok"]
+            .map(|text| Node::from_value(&serde_yaml::from_str(text).unwrap()));
+        let problems = check_documents(&documents);
+        assert_eq!(problems.len(), 1);
+        assert_eq!(problems[0].to_string(), "document 1: unknown field 'colour'");
+    }
+}
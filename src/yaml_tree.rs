@@ -0,0 +1,171 @@
+//! A YAML document tree that keeps each node's source position and scalar
+//! style, which `serde_yaml::Value` discards. Used for strict-mode checks and
+//! payload diagnostics.
+
+use anyhow::{Result, anyhow};
+use serde_yaml::Value;
+use yaml_rust2::parser::{Event, MarkedEventReceiver, Parser};
+use yaml_rust2::scanner::{Marker, TScalarStyle};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct Position {
+    pub(crate) line: usize,
+    pub(crate) column: usize,
+}
+
+impl std::fmt::Display for Position {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(formatter, "{}:{}", self.line, self.column)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum NodeKind {
+    Scalar { value: String, plain: bool },
+    Sequence(Vec<Node>),
+    Mapping(Vec<(Node, Node)>),
+    Alias,
+}
+
+#[derive(Debug)]
+pub(crate) struct Node {
+    pub(crate) kind: NodeKind,
+    /// Unknown for payloads that were not parsed from YAML (e.g. TOML).
+    pub(crate) position: Option<Position>,
+}
+
+impl Node {
+    /// Builds a tree without positions from an already parsed value.
+    pub(crate) fn from_value(value: &Value) -> Self {
+        let kind = match value {
+            Value::Null => NodeKind::Scalar {
+                value: "null".to_string(),
+                plain: true,
+            },
+            Value::Bool(value) => NodeKind::Scalar {
+                value: value.to_string(),
+                plain: true,
+            },
+            Value::Number(value) => NodeKind::Scalar {
+                value: value.to_string(),
+                plain: true,
+            },
+            Value::String(value) => NodeKind::Scalar {
+                value: value.clone(),
+                plain: false,
+            },
+            Value::Sequence(items) => NodeKind::Sequence(items.iter().map(Self::from_value).collect()),
+            Value::Mapping(entries) => NodeKind::Mapping(
+                entries
+                    .iter()
+                    .map(|(key, value)| (Self::from_value(key), Self::from_value(value)))
+                    .collect(),
+            ),
+            Value::Tagged(tagged) => return Self::from_value(&tagged.value),
+        };
+        Self {
+            kind,
+            position: None,
+        }
+    }
+
+    /// Whether this is an empty document or an explicit null.
+    pub(crate) fn is_null(&self) -> bool {
+        matches!(
+            &self.kind,
+            NodeKind::Scalar { value, plain: true } if matches!(value.as_str(), "" | "~" | "null" | "Null" | "NULL")
+        )
+    }
+
+    pub(crate) fn as_str(&self) -> Option<&str> {
+        match &self.kind {
+            NodeKind::Scalar { value, .. } => Some(value),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn get(&self, key: &str) -> Option<&Node> {
+        match &self.kind {
+            NodeKind::Mapping(entries) => entries
+                .iter()
+                .find(|(entry_key, _)| entry_key.as_str() == Some(key))
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+}
+
+/// Parses every document in `input`.
+pub(crate) fn parse_documents(input: &str) -> Result<Vec<Node>> {
+    let mut builder = TreeBuilder::default();
+    Parser::new_from_str(input)
+        .load(&mut builder, true)
+        .map_err(|error| anyhow!("{error}"))?;
+    Ok(builder.documents)
+}
+
+#[derive(Default)]
+struct TreeBuilder {
+    documents: Vec<Node>,
+    stack: Vec<Frame>,
+}
+
+/// An open collection; mappings hold their key until its value arrives.
+struct Frame {
+    node: Node,
+    pending_key: Option<Node>,
+}
+
+impl TreeBuilder {
+    fn push_node(&mut self, node: Node) {
+        let Some(frame) = self.stack.last_mut() else {
+            self.documents.push(node);
+            return;
+        };
+        match &mut frame.node.kind {
+            NodeKind::Sequence(items) => items.push(node),
+            NodeKind::Mapping(entries) => match frame.pending_key.take() {
+                Some(key) => entries.push((key, node)),
+                None => frame.pending_key = Some(node),
+            },
+            _ => unreachable!("only collections are kept on the stack"),
+        }
+    }
+
+    fn open(&mut self, kind: NodeKind, position: Option<Position>) {
+        self.stack.push(Frame {
+            node: Node { kind, position },
+            pending_key: None,
+        });
+    }
+}
+
+impl MarkedEventReceiver for TreeBuilder {
+    fn on_event(&mut self, event: Event, mark: Marker) {
+        let position = Some(Position {
+            line: mark.line(),
+            column: mark.col() + 1,
+        });
+        match event {
+            Event::Scalar(value, style, _, _) => self.push_node(Node {
+                kind: NodeKind::Scalar {
+                    value,
+                    plain: style == TScalarStyle::Plain,
+                },
+                position,
+            }),
+            Event::Alias(_) => self.push_node(Node {
+                kind: NodeKind::Alias,
+                position,
+            }),
+            Event::SequenceStart(_, _) => self.open(NodeKind::Sequence(Vec::new()), position),
+            Event::MappingStart(_, _) => self.open(NodeKind::Mapping(Vec::new()), position),
+            Event::SequenceEnd | Event::MappingEnd => {
+                if let Some(frame) = self.stack.pop() {
+                    self.push_node(frame.node);
+                }
+            }
+            _ => {}
+        }
+    }
+}
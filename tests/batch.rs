@@ -0,0 +1,38 @@
+//! Batches report a result per document, invalid documents included.
+
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+#[test]
+fn invalid_document_does_not_stop_the_batch() {
+    let dir = std::env::temp_dir().join(format!("notify-test-{}-batch", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let mut child = Command::new(env!("CARGO_BIN_EXE_notify"))
+        .args(["--dry-run", "--file", "-"])
+        .env("XDG_STATE_HOME", &dir)
+        .env("XDG_CONFIG_HOME", &dir)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"{\"summary\":\"one\"}\n{\"summary\":\"two\",\"bogus\":1}\n{\"summary\":\"three\"}\n")
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 3, "{stdout}");
+    assert!(lines[0].contains(r#""summary":"one""#), "{}", lines[0]);
+    assert!(
+        lines[1].contains(r#""document":2"#) && lines[1].contains("unknown field 'bogus'"),
+        "{}",
+        lines[1]
+    );
+    assert!(lines[2].contains(r#""summary":"three""#), "{}", lines[2]);
+}
@@ -0,0 +1,77 @@
+//! `notify config show` reports where each effective setting came from.
+
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+struct State {
+    dir: PathBuf,
+}
+
+impl State {
+    fn with_config(name: &str, config: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("notify-test-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("config.yaml"), config).unwrap();
+        Self { dir }
+    }
+
+    fn notify(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_notify"))
+            .args(args)
+            .env("NOTIFY_CONFIG", self.dir.join("config.yaml"))
+            .env("XDG_STATE_HOME", &self.dir)
+            .env("XDG_CONFIG_HOME", &self.dir)
+            .stdin(Stdio::null())
+            .output()
+            .unwrap()
+    }
+}
+
+impl Drop for State {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+const CONFIG: &str = "
+defaults:
+  urgency: low
+  expire_time: 10
+  hints: {x-a: 1, x-b: 1}
+profiles:
+  work:
+    urgency: critical
+    hints: {x-b: 2}
+";
+
+#[test]
+fn show_reports_provenance() {
+    let state = State::with_config("show", CONFIG);
+
+    let output = state.notify(&["config", "show", "--profile", "work"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let lines = stdout.lines().filter(|line| !line.starts_with('#')).collect::<Vec<_>>();
+    assert_eq!(
+        lines,
+        [
+            "hints:",
+            "  x-a: 1  # defaults",
+            "  x-b: 2  # profile work",
+            "timeout: 10  # defaults",
+            "urgency: critical  # profile work",
+        ],
+        "{stdout}"
+    );
+    assert!(stdout.contains("# profile: work\n# profiles: work\n"), "{stdout}");
+}
+
+#[test]
+fn show_rejects_unknown_profiles() {
+    let state = State::with_config("unknown", CONFIG);
+
+    let output = state.notify(&["config", "show", "--profile", "home"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown profile 'home' (available: work)"));
+}
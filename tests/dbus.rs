@@ -0,0 +1,349 @@
+//! `--await` against a mock notification server on a private bus. Skipped
+//! when `dbus-daemon` is not installed.
+
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::process::{Child, Command, Output, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use tokio::sync::mpsc;
+use zbus::object_server::SignalContext;
+use zbus::{Connection, MessageStream, interface};
+use zvariant::OwnedValue;
+
+const NAME: &str = "org.freedesktop.Notifications";
+const PATH: &str = "/org/freedesktop/Notifications";
+
+/// Set for the server that the bus activates in `bus_activated_server`.
+const ACTIVATED_ENV: &str = "NOTIFY_TEST_ACTIVATED";
+
+/// A `dbus-daemon` on a socket in its own temporary directory, which also
+/// serves as the state and config home of the `notify` runs.
+struct TestBus {
+    daemon: Child,
+    address: String,
+    dir: PathBuf,
+}
+
+impl TestBus {
+    fn start(name: &str, activatable: bool) -> Option<Self> {
+        let dir = std::env::temp_dir().join(format!("notify-test-{}-{name}", std::process::id()));
+        let services = dir.join("services");
+        std::fs::create_dir_all(&services).unwrap();
+        if activatable {
+            let test_binary = std::env::current_exe().unwrap();
+            std::fs::write(
+                services.join(format!("{NAME}.service")),
+                // The daemon's stdout is closed after the address is read.
+                format!(
+                    "[D-BUS Service]\nName={NAME}\nExec=/bin/sh -c 'exec {} activated_server --exact --ignored >/dev/null'\n",
+                    test_binary.display()
+                ),
+            )
+            .unwrap();
+        }
+        let config = dir.join("bus.conf");
+        std::fs::write(
+            &config,
+            format!(
+                r#"<!DOCTYPE busconfig PUBLIC "-//freedesktop//DTD D-Bus Bus Configuration 1.0//EN"
+ "http://www.freedesktop.org/standards/dbus/1.0/busconfig.dtd">
+<busconfig>
+  <type>session</type>
+  <listen>unix:path={}</listen>
+  <servicedir>{}</servicedir>
+  <policy context="default">
+    <allow send_destination="*" eavesdrop="true"/>
+    <allow eavesdrop="true"/>
+    <allow own="*"/>
+  </policy>
+</busconfig>
+"#,
+                dir.join("bus").display(),
+                services.display()
+            ),
+        )
+        .unwrap();
+
+        let mut daemon = match Command::new("dbus-daemon")
+            .arg(format!("--config-file={}", config.display()))
+            .args(["--nofork", "--print-address=1"])
+            .env(ACTIVATED_ENV, "1")
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+        {
+            Ok(daemon) => daemon,
+            Err(error) => {
+                eprintln!("skipping: cannot run dbus-daemon: {error}");
+                return None;
+            }
+        };
+        let mut address = String::new();
+        BufReader::new(daemon.stdout.take().unwrap())
+            .read_line(&mut address)
+            .unwrap();
+        Some(Self {
+            daemon,
+            address: address.trim().to_string(),
+            dir,
+        })
+    }
+
+    /// Runs `notify --await` for one notification with a "yes" action, in
+    /// the background so the mock servers keep answering.
+    fn notify(&self, args: &[&str]) -> tokio::task::JoinHandle<Output> {
+        let mut command = Command::new(env!("CARGO_BIN_EXE_notify"));
+        command
+            .args(["--bus-address", &self.address, "--await", "--await-timeout", "10s"])
+            .args(["--action", "yes:Yes"])
+            .args(args)
+            .arg("Summary")
+            .env("XDG_STATE_HOME", &self.dir)
+            .env("XDG_CONFIG_HOME", &self.dir)
+            .env_remove("DBUS_SESSION_BUS_ADDRESS")
+            .stdin(Stdio::null());
+        tokio::task::spawn_blocking(move || command.output().unwrap())
+    }
+}
+
+impl Drop for TestBus {
+    fn drop(&mut self) {
+        let _ = self.daemon.kill();
+        let _ = self.daemon.wait();
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Emit {
+    Nothing,
+    /// The first action, before `Notify` returns.
+    BeforeReply,
+    /// The first action, shortly after `Notify` returned.
+    AfterReply,
+}
+
+struct MockServer {
+    next_id: AtomicU32,
+    emit: Emit,
+    notified: Option<mpsc::UnboundedSender<u32>>,
+    /// Sorted hint names of each `Notify` call.
+    hinted: Option<mpsc::UnboundedSender<Vec<String>>>,
+    /// Reported by `GetServerInformation`, which fails without one.
+    spec_version: Option<&'static str>,
+}
+
+impl MockServer {
+    fn new(first_id: u32, emit: Emit) -> Self {
+        Self {
+            next_id: AtomicU32::new(first_id),
+            emit,
+            notified: None,
+            hinted: None,
+            spec_version: Some("1.2"),
+        }
+    }
+}
+
+#[interface(name = "org.freedesktop.Notifications")]
+impl MockServer {
+    #[allow(clippy::too_many_arguments)]
+    async fn notify(
+        &self,
+        #[zbus(signal_context)] context: SignalContext<'_>,
+        _app_name: String,
+        _replaces_id: u32,
+        _icon: String,
+        _summary: String,
+        _body: String,
+        actions: Vec<String>,
+        hints: HashMap<String, OwnedValue>,
+        _expire_timeout: i32,
+    ) -> u32 {
+        let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+        let action = actions.first().cloned().unwrap_or_default();
+        match self.emit {
+            Emit::Nothing => {}
+            Emit::BeforeReply => {
+                // Another notification's action first, which must be ignored.
+                Self::action_invoked(&context, id + 1000, "other").await.unwrap();
+                Self::action_invoked(&context, id, &action).await.unwrap();
+            }
+            Emit::AfterReply => {
+                let context = context.to_owned();
+                tokio::spawn(async move {
+                    tokio::time::sleep(Duration::from_millis(300)).await;
+                    Self::action_invoked(&context, id, &action).await.unwrap();
+                });
+            }
+        }
+        if let Some(notified) = &self.notified {
+            let _ = notified.send(id);
+        }
+        if let Some(hinted) = &self.hinted {
+            let mut names = hints.into_keys().collect::<Vec<_>>();
+            names.sort();
+            let _ = hinted.send(names);
+        }
+        id
+    }
+
+    fn get_capabilities(&self) -> Vec<String> {
+        vec!["actions".to_string(), "body".to_string()]
+    }
+
+    fn get_server_information(&self) -> zbus::fdo::Result<(String, String, String, String)> {
+        let version = self
+            .spec_version
+            .ok_or_else(|| zbus::fdo::Error::Failed("no server information".to_string()))?;
+        Ok(("mock".to_string(), "notify".to_string(), "1".to_string(), version.to_string()))
+    }
+
+    fn close_notification(&self, _id: u32) {}
+
+    #[zbus(signal)]
+    async fn action_invoked(context: &SignalContext<'_>, id: u32, action_key: &str) -> zbus::Result<()>;
+}
+
+async fn serve(address: &str, server: MockServer) -> Connection {
+    zbus::connection::Builder::address(address)
+        .unwrap()
+        .serve_at(PATH, server)
+        .unwrap()
+        .name(NAME)
+        .unwrap()
+        .build()
+        .await
+        .unwrap()
+}
+
+fn stdout(output: &Output) -> String {
+    String::from_utf8_lossy(&output.stdout).into_owned()
+}
+
+fn assert_action(output: &Output) {
+    assert!(
+        output.status.success(),
+        "{}\n{}",
+        output.status,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(stdout(output).contains(r#"{"action":"yes","event":"action"}"#), "{}", stdout(output));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn action_before_notify_returns() {
+    let Some(bus) = TestBus::start("early-action", false) else {
+        return;
+    };
+    let _server = serve(&bus.address, MockServer::new(1, Emit::BeforeReply)).await;
+
+    let output = bus.notify(&[]).await.unwrap();
+    assert_action(&output);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn server_lost_then_resent() {
+    let Some(bus) = TestBus::start("resend", false) else {
+        return;
+    };
+    let (notified, mut calls) = mpsc::unbounded_channel();
+    let mut first = MockServer::new(1, Emit::Nothing);
+    first.notified = Some(notified);
+    let first = serve(&bus.address, first).await;
+
+    let output = bus.notify(&["--on-server-lost", "resend"]);
+    assert_eq!(calls.recv().await, Some(1));
+    first.release_name(NAME).await.unwrap();
+    let _second = serve(&bus.address, MockServer::new(100, Emit::AfterReply)).await;
+
+    let output = output.await.unwrap();
+    assert_action(&output);
+    assert!(
+        stdout(&output).contains(r#"{"event":"resent","id":100,"previous_id":1}"#),
+        "{}",
+        stdout(&output)
+    );
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn server_lost_fails_by_default() {
+    let Some(bus) = TestBus::start("lost", false) else {
+        return;
+    };
+    let (notified, mut calls) = mpsc::unbounded_channel();
+    let mut server = MockServer::new(1, Emit::Nothing);
+    server.notified = Some(notified);
+    let server = serve(&bus.address, server).await;
+
+    let output = bus.notify(&[]);
+    assert_eq!(calls.recv().await, Some(1));
+    server.release_name(NAME).await.unwrap();
+
+    let output = output.await.unwrap();
+    assert_eq!(output.status.code(), Some(69), "{}", stdout(&output));
+    assert!(stdout(&output).contains(r#"{"event":"server-lost"}"#), "{}", stdout(&output));
+}
+
+/// Servers before spec 1.2, or that cannot say which spec they follow, get
+/// the image file as `image-path`.
+#[tokio::test(flavor = "multi_thread")]
+async fn image_path_fallback() {
+    let Some(bus) = TestBus::start("image-path", false) else {
+        return;
+    };
+    let image = bus.dir.join("icon.svg");
+    std::fs::write(&image, r#"<svg xmlns="http://www.w3.org/2000/svg" width="4" height="4"/>"#).unwrap();
+    let image = image.to_str().unwrap();
+
+    for (spec_version, expected) in [(Some("1.2"), "image-data"), (Some("1.1"), "image-path"), (None, "image-path")] {
+        let (hinted, mut calls) = mpsc::unbounded_channel();
+        let mut server = MockServer::new(1, Emit::AfterReply);
+        server.hinted = Some(hinted);
+        server.spec_version = spec_version;
+        let server = serve(&bus.address, server).await;
+
+        let output = bus.notify(&["--image", image]).await.unwrap();
+        assert_action(&output);
+        let hints = calls.recv().await.unwrap();
+        assert!(hints.contains(&expected.to_string()), "{spec_version:?}: {hints:?}");
+        assert_eq!(hints.iter().filter(|hint| hint.starts_with("image")).count(), 1, "{hints:?}");
+        assert_eq!(
+            String::from_utf8_lossy(&output.stderr).contains("warning:"),
+            spec_version.is_none()
+        );
+        server.release_name(NAME).await.unwrap();
+    }
+}
+
+/// The bus starts the server for `Notify`; its appearance is not a loss.
+#[tokio::test(flavor = "multi_thread")]
+async fn bus_activated_server() {
+    let Some(bus) = TestBus::start("activation", true) else {
+        return;
+    };
+
+    let output = bus.notify(&[]).await.unwrap();
+    assert_action(&output);
+}
+
+/// The server `bus_activated_server` has the bus start.
+#[tokio::test(flavor = "multi_thread")]
+#[ignore = "started by the bus in bus_activated_server"]
+async fn activated_server() {
+    let (Ok(address), Ok(_)) = (
+        std::env::var("DBUS_STARTER_ADDRESS"),
+        std::env::var(ACTIVATED_ENV),
+    ) else {
+        return;
+    };
+    let server = serve(&address, MockServer::new(1, Emit::AfterReply)).await;
+    // Serve until the test's bus goes away.
+    let mut messages = MessageStream::from(&server);
+    while messages.next().await.is_some() {}
+}
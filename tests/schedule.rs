@@ -0,0 +1,121 @@
+//! Only invocations that deliver may restart the scheduler for pending
+//! notifications.
+
+use std::fs::{File, TryLockError};
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// A state directory with one notification pending and no scheduler running.
+struct State {
+    dir: PathBuf,
+}
+
+impl State {
+    fn with_pending(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("notify-test-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let state = Self { dir };
+
+        let output = state.notify(&["--in", "1h", "--backend", "file", "Later"]);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+        // Hide the item until the scheduler `--in` started has exited.
+        assert!(state.wait_for_scheduler(true), "scheduler did not start");
+        let item = state.item();
+        let hidden = state.scheduled().join(".hidden");
+        std::fs::rename(&item, &hidden).unwrap();
+        assert!(state.wait_for_scheduler(false), "scheduler did not exit");
+        std::fs::rename(&hidden, &item).unwrap();
+        state
+    }
+
+    fn scheduled(&self) -> PathBuf {
+        self.dir.join("notify").join("scheduled")
+    }
+
+    fn item(&self) -> PathBuf {
+        std::fs::read_dir(self.scheduled())
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .find(|path| path.extension().is_some_and(|extension| extension == "json"))
+            .expect("a pending notification")
+    }
+
+    fn notify(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_notify"))
+            .args(args)
+            .args(["--sink-file"])
+            .arg(self.dir.join("sink.jsonl"))
+            .env("XDG_STATE_HOME", &self.dir)
+            .env("XDG_CONFIG_HOME", &self.dir)
+            .env_remove("DBUS_SESSION_BUS_ADDRESS")
+            .stdin(Stdio::null())
+            .output()
+            .unwrap()
+    }
+
+    fn run(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_notify"))
+            .args(args)
+            .env("XDG_STATE_HOME", &self.dir)
+            .env("XDG_CONFIG_HOME", &self.dir)
+            .stdin(Stdio::null())
+            .output()
+            .unwrap()
+    }
+
+    /// Waits up to two seconds for the scheduler to be running (or not).
+    fn wait_for_scheduler(&self, running: bool) -> bool {
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(2) {
+            if scheduler_running(&self.scheduled().join(".lock")) == running {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        false
+    }
+}
+
+impl Drop for State {
+    fn drop(&mut self) {
+        // Lets a started scheduler exit.
+        let _ = self.run(&["scheduled", "cancel", "--all"]);
+        self.wait_for_scheduler(false);
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+fn scheduler_running(lock_path: &Path) -> bool {
+    // Created by the first scheduler.
+    let Ok(lock) = File::options().write(true).open(lock_path) else {
+        return false;
+    };
+    matches!(lock.try_lock(), Err(TryLockError::WouldBlock))
+}
+
+#[test]
+fn read_only_commands_leave_the_scheduler_stopped() {
+    let state = State::with_pending("read-only");
+
+    let output = state.notify(&["--dry-run", "Now"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    for args in [&["schema", "payload"][..], &["scheduled", "list"], &["config", "show"]] {
+        let output = state.run(args);
+        assert!(output.status.success(), "{args:?}: {}", String::from_utf8_lossy(&output.stderr));
+    }
+
+    thread::sleep(Duration::from_millis(500));
+    assert!(!scheduler_running(&state.scheduled().join(".lock")));
+}
+
+#[test]
+fn sending_restarts_the_scheduler() {
+    let state = State::with_pending("send");
+
+    let output = state.notify(&["--backend", "file", "Now"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(state.wait_for_scheduler(true), "scheduler was not restarted");
+}
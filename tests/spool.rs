@@ -0,0 +1,72 @@
+//! `notify flush` with an unreadable entry ahead of deliverable ones.
+
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+struct State {
+    dir: PathBuf,
+}
+
+impl State {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("notify-test-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Self { dir }
+    }
+
+    fn spool(&self) -> PathBuf {
+        self.dir.join("notify").join("spool")
+    }
+
+    fn notify(&self, args: &[&str]) -> Output {
+        Command::new(env!("CARGO_BIN_EXE_notify"))
+            .args(args)
+            .env("XDG_STATE_HOME", &self.dir)
+            .env("XDG_CONFIG_HOME", &self.dir)
+            .env_remove("DBUS_SESSION_BUS_ADDRESS")
+            .stdin(Stdio::null())
+            .output()
+            .unwrap()
+    }
+}
+
+impl Drop for State {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn unreadable_entry_is_moved_aside() {
+    let state = State::new("bad-entry");
+    // The sink's directory is a file for now, so the notification is spooled.
+    let blocker = state.dir.join("sink");
+    std::fs::write(&blocker, "").unwrap();
+    let sink = blocker.join("notifications.jsonl");
+    let sink_arg = sink.to_str().unwrap();
+    for summary in ["First", "Second"] {
+        let output = state.notify(&["--spool", "--backend", "file", "--sink-file", sink_arg, summary]);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+    let bad = state.spool().join("00000000000000000000-1.json");
+    std::fs::write(&bad, r#"{"version":2}"#).unwrap();
+    std::fs::remove_file(&blocker).unwrap();
+
+    let output = state.notify(&["flush"]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("moved aside"));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.matches(r#""event":"delivered""#).count(), 2, "{stdout}");
+
+    let delivered = std::fs::read_to_string(&sink).unwrap();
+    assert!(delivered.contains("First") && delivered.contains("Second"), "{delivered}");
+    assert!(!bad.exists());
+    let mut remaining = std::fs::read_dir(state.spool())
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .filter(|name| !name.starts_with('.'))
+        .collect::<Vec<_>>();
+    remaining.sort();
+    assert_eq!(remaining, ["00000000000000000000-1.json.bad"]);
+}
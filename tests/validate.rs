@@ -0,0 +1,72 @@
+//! `notify validate` checks payloads without reading the files they name or
+//! the clock.
+
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+struct Payloads {
+    dir: PathBuf,
+}
+
+impl Payloads {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("notify-test-{}-{name}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        Self { dir }
+    }
+
+    fn validate(&self, payload: &str) -> Output {
+        let path = self.dir.join("payload.yaml");
+        std::fs::write(&path, payload).unwrap();
+        Command::new(env!("CARGO_BIN_EXE_notify"))
+            .arg("validate")
+            .arg(&path)
+            .env("XDG_STATE_HOME", &self.dir)
+            .env("XDG_CONFIG_HOME", &self.dir)
+            .stdin(Stdio::null())
+            .output()
+            .unwrap()
+    }
+}
+
+impl Drop for Payloads {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn missing_files_and_past_times_are_not_problems() {
+    let payloads = Payloads::new("validate-offline");
+    let output = payloads.validate(
+        "summary: Later
+deliver_at: 2000-01-01 09:00
+body_file: missing.txt
+image: missing.png
+sound: missing.oga
+---
+card: {type: permission, question: Go?}
+sign: true
+signing_key: missing.key
+",
+    );
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(output.status.success(), "{stdout}{stderr}");
+    assert!(stdout.ends_with("payload.yaml: ok\n"), "{stdout}");
+    for (document, field) in [(1, "body_file"), (1, "image"), (1, "sound"), (2, "signing_key")] {
+        let warning = format!("payload.yaml: document {document}: {field} file not found: ");
+        assert!(stderr.lines().any(|line| line.starts_with("warning: ") && line.contains(&warning)), "{field}: {stderr}");
+    }
+}
+
+#[test]
+fn times_must_still_parse() {
+    let payloads = Payloads::new("validate-time");
+    let output = payloads.validate("summary: Later\ndeliver_at: someday\n");
+
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stdout).contains("invalid time 'someday'"));
+}